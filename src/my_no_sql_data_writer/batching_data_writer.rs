@@ -0,0 +1,570 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use my_logger::LogEventCtx;
+use my_no_sql_server_abstractions::MyNoSqlEntity;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{sync::watch, task::JoinHandle};
+
+use super::{DataWriterError, ErrorContext, MyNoSqlDataWriterTrait};
+
+#[derive(Debug, Clone)]
+pub struct BatchingParams {
+    // Buffer is flushed as soon as it holds that amount of distinct rows.
+    // It is also the most rows one request carries
+    pub max_batch_size: usize,
+    // Rows wait in the buffer while flushes fail. New rows are rejected with BufferIsFull above it
+    pub max_buffer_size: usize,
+    // Shorter period is raised to MIN_FLUSH_PERIOD
+    pub flush_period: Duration,
+}
+
+pub const MIN_FLUSH_PERIOD: Duration = Duration::from_millis(10);
+
+impl Default for BatchingParams {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 1000,
+            max_buffer_size: 100_000,
+            flush_period: Duration::from_secs(1),
+        }
+    }
+}
+
+// partition_key, row_key -> last written entity
+type Buffer<TEntity> = HashMap<(String, String), TEntity>;
+
+// Entities taken for a flush go back to the buffer unless they are written.
+// It is done on drop, so a cancelled flush does not lose them either
+struct TakenEntities<'s, TEntity: MyNoSqlEntity> {
+    buffer: &'s Mutex<Buffer<TEntity>>,
+    entities: Vec<TEntity>,
+}
+
+impl<'s, TEntity: MyNoSqlEntity> Drop for TakenEntities<'s, TEntity> {
+    fn drop(&mut self) {
+        if self.entities.is_empty() {
+            return;
+        }
+
+        let mut buffer = self.buffer.lock().unwrap();
+
+        // Rows which are written again meanwhile keep the newer value
+        for entity in self.entities.drain(..) {
+            let key = (
+                entity.get_partition_key().to_string(),
+                entity.get_row_key().to_string(),
+            );
+
+            buffer.entry(key).or_insert(entity);
+        }
+    }
+}
+
+struct BatchingDataWriterInner<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+{
+    writer: Arc<dyn MyNoSqlDataWriterTrait<TEntity> + Send + Sync>,
+    buffer: Mutex<Buffer<TEntity>>,
+    // Flushes go one by one, so an older value of a row never overtakes a newer one
+    flush_lock: tokio::sync::Mutex<()>,
+    max_batch_size: usize,
+    max_buffer_size: usize,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    BatchingDataWriterInner<TEntity>
+{
+    fn get_pending_amount(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    // Sends the rows which are in the buffer at the start in batches of max_batch_size.
+    // Rows written meanwhile wait for the next flush, so a busy producer does not keep it going
+    async fn flush(&self) -> Result<(), DataWriterError> {
+        let _flush_guard = self.flush_lock.lock().await;
+
+        let mut rows_to_flush = self.get_pending_amount();
+
+        while rows_to_flush > 0 {
+            let mut taken = TakenEntities {
+                buffer: &self.buffer,
+                entities: self.take_batch(rows_to_flush.min(self.max_batch_size)),
+            };
+
+            if taken.entities.is_empty() {
+                return Ok(());
+            }
+
+            rows_to_flush = rows_to_flush.saturating_sub(taken.entities.len());
+
+            self.writer.bulk_insert_or_replace(&taken.entities).await?;
+            taken.entities.clear();
+        }
+
+        Ok(())
+    }
+
+    fn take_batch(&self, amount: usize) -> Vec<TEntity> {
+        let mut buffer = self.buffer.lock().unwrap();
+
+        let keys: Vec<_> = buffer.keys().take(amount).cloned().collect();
+
+        keys.iter().filter_map(|key| buffer.remove(key)).collect()
+    }
+}
+
+/// Buffers writes and sends them with `bulk_insert_or_replace` when `max_batch_size` distinct
+/// rows are collected or every `flush_period`. Repeated writes of a row are coalesced.
+/// A request never carries more than `max_batch_size` rows.
+///
+/// Entities of a failed flush stay in the buffer and are sent with the next one.
+/// Call `shutdown` to write the rest of the buffer - on drop the final flush is only spawned.
+pub struct BatchingDataWriter<
+    TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static,
+> {
+    inner: Arc<BatchingDataWriterInner<TEntity>>,
+    stop_timer: watch::Sender<bool>,
+    flush_timer: Option<JoinHandle<()>>,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static>
+    BatchingDataWriter<TEntity>
+{
+    pub fn new(
+        writer: Arc<dyn MyNoSqlDataWriterTrait<TEntity> + Send + Sync>,
+        params: BatchingParams,
+    ) -> Self {
+        let max_batch_size = params.max_batch_size.max(1);
+
+        let inner = Arc::new(BatchingDataWriterInner {
+            writer,
+            buffer: Mutex::new(HashMap::new()),
+            flush_lock: tokio::sync::Mutex::new(()),
+            max_batch_size,
+            max_buffer_size: params.max_buffer_size.max(max_batch_size),
+        });
+
+        let (stop_timer, stop_receiver) = watch::channel(false);
+
+        let flush_timer = tokio::spawn(flush_by_timer(
+            Arc::downgrade(&inner),
+            params.flush_period.max(MIN_FLUSH_PERIOD),
+            stop_receiver,
+        ));
+
+        Self {
+            inner,
+            stop_timer,
+            flush_timer: Some(flush_timer),
+        }
+    }
+
+    pub fn get_pending_amount(&self) -> usize {
+        self.inner.get_pending_amount()
+    }
+
+    /// Buffers the entity. If it makes the buffer reach `max_batch_size`, the flush is awaited.
+    /// Failure of that flush is logged and not returned - the entity is accepted and is sent
+    /// with the next flush. Error is returned only if the buffer is full and the entity is
+    /// rejected.
+    pub async fn insert_or_replace_entity(&self, entity: TEntity) -> Result<(), DataWriterError> {
+        let pending_amount = {
+            let mut buffer = self.inner.buffer.lock().unwrap();
+
+            let key = (
+                entity.get_partition_key().to_string(),
+                entity.get_row_key().to_string(),
+            );
+
+            if buffer.len() >= self.inner.max_buffer_size && !buffer.contains_key(&key) {
+                return Err(DataWriterError::BufferIsFull {
+                    ctx: ErrorContext::new(
+                        "batching_insert_or_replace",
+                        TEntity::TABLE_NAME,
+                        String::new(),
+                    ),
+                    message: format!("{} rows are waiting to be flushed", buffer.len()),
+                });
+            }
+
+            buffer.insert(key, entity);
+            buffer.len()
+        };
+
+        if pending_amount >= self.inner.max_batch_size {
+            if let Err(err) = self.inner.flush().await {
+                write_flush_error::<TEntity>(&err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops at the first entity which is rejected because the buffer is full.
+    pub async fn bulk_insert_or_replace(
+        &self,
+        entities: Vec<TEntity>,
+    ) -> Result<(), DataWriterError> {
+        for entity in entities {
+            self.insert_or_replace_entity(entity).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends everything buffered so far, `max_batch_size` rows per request.
+    /// Stops at the first failed request - its rows and the rows not sent yet stay in the buffer.
+    pub async fn flush(&self) -> Result<(), DataWriterError> {
+        self.inner.flush().await
+    }
+
+    /// Stops the timer and writes the rest of the buffer.
+    /// Flush which the timer has already started is finished first.
+    pub async fn shutdown(mut self) -> Result<(), DataWriterError> {
+        if let Some(flush_timer) = self.flush_timer.take() {
+            let _ = self.stop_timer.send(true);
+            let _ = flush_timer.await;
+        }
+
+        self.inner.flush().await
+    }
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static> Drop
+    for BatchingDataWriter<TEntity>
+{
+    fn drop(&mut self) {
+        let flush_timer = match self.flush_timer.take() {
+            Some(flush_timer) => flush_timer,
+            // shutdown is already done
+            None => return,
+        };
+
+        let _ = self.stop_timer.send(true);
+
+        let inner = self.inner.clone();
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    // Entities of a failed timer flush are back in the buffer once it is finished
+                    let _ = flush_timer.await;
+
+                    if let Err(err) = inner.flush().await {
+                        write_flush_error::<TEntity>(&err);
+                    }
+                });
+            }
+            Err(_) => {
+                if inner.get_pending_amount() == 0 {
+                    return;
+                }
+
+                my_logger::LOGGER.write_error(
+                    "BatchingDataWriter",
+                    format!(
+                        "Dropped outside of tokio runtime. {} entities are lost",
+                        inner.get_pending_amount()
+                    ),
+                    LogEventCtx::new().add("TableName", TEntity::TABLE_NAME),
+                );
+            }
+        }
+    }
+}
+
+// Holds a weak reference, so the timer does not keep a dropped writer alive.
+// Stop is checked only between flushes, so a flush in progress is never interrupted
+async fn flush_by_timer<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>(
+    inner: Weak<BatchingDataWriterInner<TEntity>>,
+    flush_period: Duration,
+    mut stop: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(flush_period) => {}
+            _ = stop.changed() => return,
+        }
+
+        let inner = match inner.upgrade() {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        if let Err(err) = inner.flush().await {
+            write_flush_error::<TEntity>(&err);
+        }
+    }
+}
+
+fn write_flush_error<TEntity: MyNoSqlEntity>(err: &DataWriterError) {
+    my_logger::LOGGER.write_error(
+        "BatchingDataWriter",
+        format!("Failed to flush entities: {}", err),
+        LogEventCtx::new().add("TableName", TEntity::TABLE_NAME),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{BatchingDataWriter, BatchingParams, MIN_FLUSH_PERIOD};
+    use crate::{
+        test_fixtures::{create_writer_with_retry_policy, TestEntity},
+        DataWriterError, InMemoryDataWriter, MockFault, MockMyNoSqlServer, MyNoSqlDataWriterTrait,
+        RetryPolicy,
+    };
+
+    #[tokio::test]
+    async fn test_writes_are_coalesced_and_flushed_by_size() {
+        let in_memory: Arc<InMemoryDataWriter<TestEntity>> = Arc::new(InMemoryDataWriter::new());
+
+        let writer = BatchingDataWriter::new(
+            in_memory.clone(),
+            BatchingParams {
+                max_batch_size: 2,
+                flush_period: Duration::from_secs(60),
+                ..Default::default()
+            },
+        );
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 2))
+            .await
+            .unwrap();
+
+        assert_eq!(writer.get_pending_amount(), 1);
+        assert_eq!(in_memory.get_rows_amount().await, 0);
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk2", 3))
+            .await
+            .unwrap();
+
+        assert_eq!(writer.get_pending_amount(), 0);
+        assert_eq!(in_memory.get_rows_amount().await, 2);
+
+        let entity = in_memory.get_entity("pk", "rk1", None).await.unwrap();
+        assert_eq!(entity.unwrap().value, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_by_timer_and_on_shutdown() {
+        let in_memory: Arc<InMemoryDataWriter<TestEntity>> = Arc::new(InMemoryDataWriter::new());
+
+        let writer = BatchingDataWriter::new(
+            in_memory.clone(),
+            BatchingParams {
+                max_batch_size: 100,
+                flush_period: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(in_memory.get_rows_amount().await, 0);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(in_memory.get_rows_amount().await, 1);
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk2", 2))
+            .await
+            .unwrap();
+
+        writer.shutdown().await.unwrap();
+        assert_eq!(in_memory.get_rows_amount().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_entities_and_buffer_is_limited() {
+        let server = MockMyNoSqlServer::start().await;
+        let my_no_sql_writer = Arc::new(create_writer_with_retry_policy(
+            &server,
+            RetryPolicy::no_retries(),
+        ));
+
+        let writer = BatchingDataWriter::new(
+            my_no_sql_writer.clone(),
+            BatchingParams {
+                max_batch_size: 2,
+                max_buffer_size: 3,
+                flush_period: Duration::from_secs(60),
+            },
+        );
+
+        for _ in 0..2 {
+            server.inject_fault_for_path(
+                "/Bulk/InsertOrReplace",
+                MockFault::ServerError {
+                    status: 500,
+                    body: "Not available".to_string(),
+                },
+            );
+        }
+
+        // Flushes triggered by size fail, but the entities are accepted and stay in the buffer
+        for (row_key, value) in [("rk1", 1), ("rk2", 2), ("rk3", 3)] {
+            writer
+                .insert_or_replace_entity(TestEntity::new("pk", row_key, value))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(writer.get_pending_amount(), 3);
+        assert_eq!(server.get_rows_amount("test"), 0);
+
+        let result = writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk4", 4))
+            .await;
+        assert!(matches!(result, Err(DataWriterError::BufferIsFull { .. })));
+
+        // Row which is already in the buffer is still accepted
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 5))
+            .await
+            .unwrap();
+
+        assert_eq!(writer.get_pending_amount(), 0);
+        assert_eq!(server.get_rows_amount("test"), 3);
+
+        let entity = my_no_sql_writer
+            .get_entity("pk", "rk1", None)
+            .await
+            .unwrap();
+        assert_eq!(entity.unwrap().value, 5);
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_at_most_max_batch_size_per_request() {
+        let server = MockMyNoSqlServer::start().await;
+        let my_no_sql_writer = Arc::new(create_writer_with_retry_policy(
+            &server,
+            RetryPolicy::no_retries(),
+        ));
+
+        let writer = BatchingDataWriter::new(
+            my_no_sql_writer,
+            BatchingParams {
+                max_batch_size: 2,
+                max_buffer_size: 10,
+                flush_period: Duration::from_secs(60),
+            },
+        );
+
+        // Flushes triggered by size fail, so the buffer grows above max_batch_size
+        server.inject_faults(
+            MockFault::ServerError {
+                status: 500,
+                body: "Not available".to_string(),
+            },
+            4,
+        );
+
+        for i in 0..5 {
+            writer
+                .insert_or_replace_entity(TestEntity::new("pk", &format!("rk{}", i), i))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(writer.get_pending_amount(), 5);
+
+        // First batch is written and the second one fails, so it and the third one stay
+        server.inject_fault_for_path("/Bulk/InsertOrReplace", MockFault::Delay(Duration::ZERO));
+        server.inject_fault_for_path(
+            "/Bulk/InsertOrReplace",
+            MockFault::ServerError {
+                status: 500,
+                body: "Not available".to_string(),
+            },
+        );
+
+        let result = writer.flush().await;
+        assert!(result.is_err());
+        assert_eq!(writer.get_pending_amount(), 3);
+        assert_eq!(server.get_rows_amount("test"), 2);
+
+        writer.flush().await.unwrap();
+        assert_eq!(writer.get_pending_amount(), 0);
+        assert_eq!(server.get_rows_amount("test"), 5);
+
+        let batch_sizes: Vec<usize> = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Bulk/InsertOrReplace")
+            .map(|request| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&request.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert!(batch_sizes.iter().all(|size| *size <= 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_zero_flush_period_is_raised() {
+        let in_memory: Arc<InMemoryDataWriter<TestEntity>> = Arc::new(InMemoryDataWriter::new());
+
+        let writer = BatchingDataWriter::new(
+            in_memory.clone(),
+            BatchingParams {
+                max_batch_size: 100,
+                flush_period: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(MIN_FLUSH_PERIOD / 2).await;
+        assert_eq!(in_memory.get_rows_amount().await, 0);
+
+        tokio::time::sleep(MIN_FLUSH_PERIOD).await;
+        assert_eq!(in_memory.get_rows_amount().await, 1);
+
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_stops_timer() {
+        let in_memory: Arc<InMemoryDataWriter<TestEntity>> = Arc::new(InMemoryDataWriter::new());
+
+        let writer = BatchingDataWriter::new(
+            in_memory.clone(),
+            BatchingParams {
+                max_batch_size: 100,
+                flush_period: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+
+        writer
+            .insert_or_replace_entity(TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+
+        let inner = Arc::downgrade(&writer.inner);
+        writer.shutdown().await.unwrap();
+
+        assert_eq!(in_memory.get_rows_amount().await, 1);
+        // Timer task is finished, so nothing holds the buffer any more
+        assert!(inner.upgrade().is_none());
+    }
+}
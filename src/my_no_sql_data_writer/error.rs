@@ -2,41 +2,304 @@ use std::{str::Utf8Error, string::FromUtf8Error};
 
 use flurl::FlUrlError;
 
+use super::RetryPolicy;
+
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    pub operation: &'static str,
+    pub table_name: &'static str,
+    pub url: String,
+    pub status_code: Option<u16>,
+}
+
+impl ErrorContext {
+    pub fn new(operation: &'static str, table_name: &'static str, url: String) -> Self {
+        Self {
+            operation,
+            table_name,
+            url,
+            status_code: None,
+        }
+    }
+
+    pub fn with_status_code(mut self, status_code: u16) -> Self {
+        self.status_code = Some(status_code);
+        self
+    }
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Operation: {}. Table: {}. Url: {}",
+            self.operation, self.table_name, self.url
+        )?;
+
+        if let Some(status_code) = self.status_code {
+            write!(f, ". Status code: {}", status_code)?;
+        }
+
+        Ok(())
+    }
+}
+
+// FlUrlError does not implement std::error::Error, so it is wrapped to be able to chain it as a source
+#[derive(Debug)]
+pub struct FlUrlErrorSource(pub FlUrlError);
+
+impl std::fmt::Display for FlUrlErrorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::error::Error for FlUrlErrorSource {}
+
 #[derive(Debug)]
 pub enum DataWriterError {
-    TableAlreadyExists(String),
-    TableNotFound(String),
-    RecordAlreadyExists(String),
-    RecordIsChanged(String),
-    RequiredEntityFieldIsMissing(String),
-    ServerCouldNotParseJson(String),
-    FromUtf8Error(FromUtf8Error),
-    Utf8Error(Utf8Error),
-    Error(String),
-    FlUrlError(FlUrlError),
-    HyperError(hyper::Error),
+    TableAlreadyExists {
+        ctx: ErrorContext,
+        message: String,
+    },
+    TableNotFound {
+        ctx: ErrorContext,
+        message: String,
+    },
+    RecordAlreadyExists {
+        ctx: ErrorContext,
+        message: String,
+    },
+    RecordIsChanged {
+        ctx: ErrorContext,
+        message: String,
+    },
+    RecordNotFound {
+        ctx: ErrorContext,
+        message: String,
+    },
+    RequiredEntityFieldIsMissing {
+        ctx: ErrorContext,
+        message: String,
+    },
+    ServerCouldNotParseJson {
+        ctx: ErrorContext,
+        message: String,
+    },
+    FromUtf8Error {
+        ctx: ErrorContext,
+        err: FromUtf8Error,
+    },
+    Utf8Error {
+        ctx: ErrorContext,
+        err: Utf8Error,
+    },
+    Error {
+        ctx: ErrorContext,
+        message: String,
+    },
+    FlUrlError {
+        ctx: ErrorContext,
+        err: FlUrlErrorSource,
+    },
+    HyperError {
+        ctx: ErrorContext,
+        err: hyper::Error,
+    },
+    ServerError {
+        ctx: ErrorContext,
+        status: u16,
+        body: String,
+    },
+    Timeout {
+        ctx: ErrorContext,
+    },
+    SerializationFailed {
+        ctx: ErrorContext,
+        err: serde_json::Error,
+    },
+    SpoolIsFull {
+        ctx: ErrorContext,
+        message: String,
+    },
+    BufferIsFull {
+        ctx: ErrorContext,
+        message: String,
+    },
+    SpoolIoError {
+        ctx: ErrorContext,
+        path: String,
+        err: std::io::Error,
+    },
+}
+
+impl DataWriterError {
+    pub fn from_fl_url_error(ctx: ErrorContext, err: FlUrlError) -> Self {
+        Self::FlUrlError {
+            ctx,
+            err: FlUrlErrorSource(err),
+        }
+    }
+
+    pub fn get_ctx(&self) -> &ErrorContext {
+        match self {
+            Self::TableAlreadyExists { ctx, .. } => ctx,
+            Self::TableNotFound { ctx, .. } => ctx,
+            Self::RecordAlreadyExists { ctx, .. } => ctx,
+            Self::RecordIsChanged { ctx, .. } => ctx,
+            Self::RecordNotFound { ctx, .. } => ctx,
+            Self::RequiredEntityFieldIsMissing { ctx, .. } => ctx,
+            Self::ServerCouldNotParseJson { ctx, .. } => ctx,
+            Self::FromUtf8Error { ctx, .. } => ctx,
+            Self::Utf8Error { ctx, .. } => ctx,
+            Self::Error { ctx, .. } => ctx,
+            Self::FlUrlError { ctx, .. } => ctx,
+            Self::HyperError { ctx, .. } => ctx,
+            Self::ServerError { ctx, .. } => ctx,
+            Self::Timeout { ctx } => ctx,
+            Self::SerializationFailed { ctx, .. } => ctx,
+            Self::SpoolIsFull { ctx, .. } => ctx,
+            Self::BufferIsFull { ctx, .. } => ctx,
+            Self::SpoolIoError { ctx, .. } => ctx,
+        }
+    }
+
+    pub fn get_status_code(&self) -> Option<u16> {
+        self.get_ctx().status_code
+    }
+
+    // Decided by the default RetryPolicy. With a custom one use RetryPolicy::is_retryable_error
+    pub fn is_retryable(&self) -> bool {
+        RetryPolicy::default().is_retryable_error(self)
+    }
+}
+
+impl std::fmt::Display for DataWriterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TableAlreadyExists { ctx, message } => {
+                write!(f, "Table already exists: {}. {}", message, ctx)
+            }
+            Self::TableNotFound { ctx, message } => {
+                write!(f, "Table not found: {}. {}", message, ctx)
+            }
+            Self::RecordAlreadyExists { ctx, message } => {
+                write!(f, "Record already exists: {}. {}", message, ctx)
+            }
+            Self::RecordIsChanged { ctx, message } => {
+                write!(f, "Record is changed: {}. {}", message, ctx)
+            }
+            Self::RecordNotFound { ctx, message } => {
+                write!(f, "Record not found: {}. {}", message, ctx)
+            }
+            Self::RequiredEntityFieldIsMissing { ctx, message } => {
+                write!(f, "Required entity field is missing: {}. {}", message, ctx)
+            }
+            Self::ServerCouldNotParseJson { ctx, message } => {
+                write!(f, "Server could not parse json: {}. {}", message, ctx)
+            }
+            Self::FromUtf8Error { ctx, err } => write!(f, "Invalid utf8: {}. {}", err, ctx),
+            Self::Utf8Error { ctx, err } => write!(f, "Invalid utf8: {}. {}", err, ctx),
+            Self::Error { ctx, message } => write!(f, "{}. {}", message, ctx),
+            Self::FlUrlError { ctx, err } => write!(f, "Http request failed: {}. {}", err, ctx),
+            Self::HyperError { ctx, err } => write!(f, "Http request failed: {}. {}", err, ctx),
+            Self::ServerError { ctx, body, .. } => write!(f, "Server error: {}. {}", body, ctx),
+            Self::Timeout { ctx } => write!(f, "Timeout. {}", ctx),
+            Self::SerializationFailed { ctx, err } => {
+                write!(f, "Failed to serialize entity: {}. {}", err, ctx)
+            }
+            Self::SpoolIsFull { ctx, message } => write!(f, "Spool is full: {}. {}", message, ctx),
+            Self::BufferIsFull { ctx, message } => {
+                write!(f, "Buffer is full: {}. {}", message, ctx)
+            }
+            Self::SpoolIoError { ctx, path, err } => {
+                write!(f, "Spool io error at {}: {}. {}", path, err, ctx)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataWriterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::FromUtf8Error { err, .. } => Some(err),
+            Self::Utf8Error { err, .. } => Some(err),
+            Self::FlUrlError { err, .. } => Some(err),
+            Self::HyperError { err, .. } => Some(err),
+            Self::SerializationFailed { err, .. } => Some(err),
+            Self::SpoolIoError { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Conversions without a context are kept for the code which uses `?` on these errors
+impl From<FlUrlError> for DataWriterError {
+    fn from(src: FlUrlError) -> Self {
+        Self::from_fl_url_error(ErrorContext::default(), src)
+    }
 }
 
 impl From<hyper::Error> for DataWriterError {
     fn from(src: hyper::Error) -> Self {
-        Self::HyperError(src)
+        Self::HyperError {
+            ctx: ErrorContext::default(),
+            err: src,
+        }
     }
 }
 
 impl From<FromUtf8Error> for DataWriterError {
     fn from(src: FromUtf8Error) -> Self {
-        Self::FromUtf8Error(src)
+        Self::FromUtf8Error {
+            ctx: ErrorContext::default(),
+            err: src,
+        }
     }
 }
 
 impl From<Utf8Error> for DataWriterError {
     fn from(src: Utf8Error) -> Self {
-        Self::Utf8Error(src)
+        Self::Utf8Error {
+            ctx: ErrorContext::default(),
+            err: src,
+        }
     }
 }
 
-impl From<FlUrlError> for DataWriterError {
-    fn from(src: FlUrlError) -> Self {
-        Self::FlUrlError(src)
+impl From<serde_json::Error> for DataWriterError {
+    fn from(src: serde_json::Error) -> Self {
+        Self::SerializationFailed {
+            ctx: ErrorContext::default(),
+            err: src,
+        }
+    }
+}
+
+pub(crate) trait IntoDataWriterError {
+    fn into_data_writer_error(self, ctx: ErrorContext) -> DataWriterError;
+}
+
+impl IntoDataWriterError for FlUrlError {
+    fn into_data_writer_error(self, ctx: ErrorContext) -> DataWriterError {
+        DataWriterError::from_fl_url_error(ctx, self)
+    }
+}
+
+impl IntoDataWriterError for hyper::Error {
+    fn into_data_writer_error(self, ctx: ErrorContext) -> DataWriterError {
+        DataWriterError::HyperError { ctx, err: self }
+    }
+}
+
+impl IntoDataWriterError for FromUtf8Error {
+    fn into_data_writer_error(self, ctx: ErrorContext) -> DataWriterError {
+        DataWriterError::FromUtf8Error { ctx, err: self }
+    }
+}
+
+impl IntoDataWriterError for Utf8Error {
+    fn into_data_writer_error(self, ctx: ErrorContext) -> DataWriterError {
+        DataWriterError::Utf8Error { ctx, err: self }
     }
 }
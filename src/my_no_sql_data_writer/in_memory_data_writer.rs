@@ -0,0 +1,505 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    ops::Bound,
+};
+
+use my_no_sql_server_abstractions::MyNoSqlEntity;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
+
+use super::{DataWriterError, ErrorContext, MyNoSqlDataWriterTrait, UpdateReadStatistics};
+
+const IN_MEMORY_URL: &str = "in-memory";
+
+// Entities are kept serialized the same way they travel to the server, so TEntity does not have to be Clone.
+// partition_key -> row_key -> entity
+type Partitions = BTreeMap<String, BTreeMap<String, Vec<u8>>>;
+
+/// Fake of `MyNoSqlDataWriter` which keeps the table in memory and reproduces the server semantics.
+/// Entities keep the timestamps they were written with - the fake does not assign new ones.
+pub struct InMemoryDataWriter<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize> {
+    partitions: Mutex<Partitions>,
+    itm: PhantomData<TEntity>,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    InMemoryDataWriter<TEntity>
+{
+    pub fn new() -> Self {
+        Self {
+            partitions: Mutex::new(BTreeMap::new()),
+            itm: PhantomData,
+        }
+    }
+
+    pub async fn get_rows_amount(&self) -> usize {
+        let partitions = self.partitions.lock().await;
+        partitions.values().map(|rows| rows.len()).sum()
+    }
+
+    fn get_ctx(process_name: &'static str) -> ErrorContext {
+        ErrorContext::new(process_name, TEntity::TABLE_NAME, IN_MEMORY_URL.to_string())
+    }
+
+    fn serialize(entity: &TEntity, process_name: &'static str) -> Result<Vec<u8>, DataWriterError> {
+        serde_json::to_vec(entity).map_err(|err| DataWriterError::SerializationFailed {
+            ctx: Self::get_ctx(process_name),
+            err,
+        })
+    }
+
+    fn deserialize(src: &[u8], process_name: &'static str) -> Result<TEntity, DataWriterError> {
+        serde_json::from_slice(src).map_err(|err| DataWriterError::Error {
+            ctx: Self::get_ctx(process_name),
+            message: format!("Failed to deserialize entity: {:?}", err),
+        })
+    }
+
+    fn deserialize_rows<'s>(
+        rows: impl Iterator<Item = &'s Vec<u8>>,
+        process_name: &'static str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let mut result = Vec::new();
+
+        for row in rows {
+            result.push(Self::deserialize(row, process_name)?);
+        }
+
+        Ok(result)
+    }
+
+    fn insert_rows(
+        partitions: &mut Partitions,
+        entities: &[TEntity],
+        process_name: &'static str,
+    ) -> Result<(), DataWriterError> {
+        let mut rows = Vec::with_capacity(entities.len());
+
+        // Everything is serialized first, so a failure does not leave half of the entities written
+        for entity in entities {
+            rows.push((entity, Self::serialize(entity, process_name)?));
+        }
+
+        for (entity, row) in rows {
+            partitions
+                .entry(entity.get_partition_key().to_string())
+                .or_default()
+                .insert(entity.get_row_key().to_string(), row);
+        }
+
+        Ok(())
+    }
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize> Default
+    for InMemoryDataWriter<TEntity>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    MyNoSqlDataWriterTrait<TEntity> for InMemoryDataWriter<TEntity>
+{
+    async fn insert_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        let row = Self::serialize(entity, "insert_entity")?;
+
+        let mut partitions = self.partitions.lock().await;
+
+        let rows = partitions
+            .entry(entity.get_partition_key().to_string())
+            .or_default();
+
+        if rows.contains_key(entity.get_row_key()) {
+            return Err(DataWriterError::RecordAlreadyExists {
+                ctx: Self::get_ctx("insert_entity"),
+                message: format!(
+                    "Record with PartitionKey: {} and RowKey: {} already exists",
+                    entity.get_partition_key(),
+                    entity.get_row_key()
+                ),
+            });
+        }
+
+        rows.insert(entity.get_row_key().to_string(), row);
+
+        Ok(())
+    }
+
+    async fn insert_or_replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        let mut partitions = self.partitions.lock().await;
+        Self::insert_rows(
+            &mut partitions,
+            std::slice::from_ref(entity),
+            "insert_or_replace_entity",
+        )
+    }
+
+    async fn replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        let row = Self::serialize(entity, "replace_entity")?;
+
+        let mut partitions = self.partitions.lock().await;
+
+        let existing_row = partitions
+            .get_mut(entity.get_partition_key())
+            .and_then(|rows| rows.get_mut(entity.get_row_key()));
+
+        let existing_row = match existing_row {
+            Some(existing_row) => existing_row,
+            None => {
+                return Err(DataWriterError::RecordNotFound {
+                    ctx: Self::get_ctx("replace_entity"),
+                    message: format!(
+                        "Record with PartitionKey: {} and RowKey: {} is not found",
+                        entity.get_partition_key(),
+                        entity.get_row_key()
+                    ),
+                })
+            }
+        };
+
+        let existing_entity = Self::deserialize(existing_row, "replace_entity")?;
+
+        if existing_entity.get_time_stamp() != entity.get_time_stamp() {
+            return Err(DataWriterError::RecordIsChanged {
+                ctx: Self::get_ctx("replace_entity"),
+                message: format!(
+                    "Expected timestamp: {}. Actual timestamp: {}",
+                    entity.get_time_stamp(),
+                    existing_entity.get_time_stamp()
+                ),
+            });
+        }
+
+        *existing_row = row;
+
+        Ok(())
+    }
+
+    async fn bulk_insert_or_replace(&self, entities: &[TEntity]) -> Result<(), DataWriterError> {
+        let mut partitions = self.partitions.lock().await;
+        Self::insert_rows(&mut partitions, entities, "bulk_insert_or_replace")
+    }
+
+    async fn get_entity(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        _update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions
+            .get(partition_key)
+            .and_then(|rows| rows.get(row_key))
+        {
+            Some(row) => Ok(Some(Self::deserialize(row, "get_entity")?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_entities(
+        &self,
+        keys: &[(&str, &str)],
+        _update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<HashMap<(String, String), Option<TEntity>>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+        let mut result = HashMap::new();
+
+        for (partition_key, row_key) in keys {
+            let entity = match partitions
+                .get(*partition_key)
+                .and_then(|rows| rows.get(*row_key))
+            {
+                Some(row) => Some(Self::deserialize(row, "get_entities")?),
+                None => None,
+            };
+
+            result.insert((partition_key.to_string(), row_key.to_string()), entity);
+        }
+
+        Ok(result)
+    }
+
+    async fn get_by_partition_key(
+        &self,
+        partition_key: &str,
+        _update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions.get(partition_key) {
+            Some(rows) => Ok(Some(Self::deserialize_rows(
+                rows.values(),
+                "get_by_partition_key",
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_by_partition_key_page(
+        &self,
+        partition_key: &str,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions.get(partition_key) {
+            Some(rows) => Self::deserialize_rows(
+                rows.values().skip(skip).take(limit),
+                "get_by_partition_key_page",
+            ),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_highest_row_and_below(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        max_amount: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions.get(partition_key) {
+            Some(rows) => Self::deserialize_rows(
+                rows.range::<str, _>((Bound::Unbounded, Bound::Included(row_key)))
+                    .rev()
+                    .take(max_amount)
+                    .map(|(_, row)| row),
+                "get_highest_row_and_below",
+            ),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_by_row_key_range(
+        &self,
+        partition_key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions.get(partition_key) {
+            Some(rows) if from <= to => Self::deserialize_rows(
+                rows.range::<str, _>((Bound::Included(from), Bound::Included(to)))
+                    .map(|(_, row)| row),
+                "get_by_row_key_range",
+            ),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_by_row_key_prefix(
+        &self,
+        partition_key: &str,
+        prefix: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        match partitions.get(partition_key) {
+            Some(rows) => Self::deserialize_rows(
+                rows.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                    .take_while(|(row_key, _)| row_key.starts_with(prefix))
+                    .map(|(_, row)| row),
+                "get_by_row_key_prefix",
+            ),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_by_row_key(&self, row_key: &str) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        let rows = partitions.values().filter_map(|rows| rows.get(row_key));
+        let entities = Self::deserialize_rows(rows, "get_by_row_key")?;
+
+        // Server answers 404 when no partition has the row
+        if entities.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(entities))
+    }
+
+    async fn get_all(&self) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        let rows = partitions.values().flat_map(|rows| rows.values());
+
+        Ok(Some(Self::deserialize_rows(rows, "get_all")?))
+    }
+
+    async fn get_all_page(
+        &self,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let partitions = self.partitions.lock().await;
+
+        let rows = partitions
+            .values()
+            .flat_map(|rows| rows.values())
+            .skip(skip)
+            .take(limit);
+
+        Self::deserialize_rows(rows, "get_all_page")
+    }
+
+    async fn delete_row(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+    ) -> Result<Option<TEntity>, DataWriterError> {
+        let mut partitions = self.partitions.lock().await;
+
+        let rows = match partitions.get_mut(partition_key) {
+            Some(rows) => rows,
+            None => return Ok(None),
+        };
+
+        let removed_row = rows.remove(row_key);
+
+        if rows.is_empty() {
+            partitions.remove(partition_key);
+        }
+
+        match removed_row {
+            Some(row) => Ok(Some(Self::deserialize(&row, "delete_row")?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_partitions(&self, partition_keys: &[&str]) -> Result<(), DataWriterError> {
+        let mut partitions = self.partitions.lock().await;
+
+        for partition_key in partition_keys {
+            partitions.remove(*partition_key);
+        }
+
+        Ok(())
+    }
+
+    async fn bulk_delete_rows(
+        &self,
+        keys: &[(&str, &str)],
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        let mut partitions = self.partitions.lock().await;
+        let mut removed_rows = Vec::new();
+
+        for (partition_key, row_key) in keys {
+            let rows = match partitions.get_mut(*partition_key) {
+                Some(rows) => rows,
+                None => continue,
+            };
+
+            if let Some(row) = rows.remove(*row_key) {
+                removed_rows.push(row);
+            }
+
+            if rows.is_empty() {
+                partitions.remove(*partition_key);
+            }
+        }
+
+        let removed_rows = Self::deserialize_rows(removed_rows.iter(), "bulk_delete_rows")?;
+        Ok(Some(removed_rows))
+    }
+
+    async fn clean_table_and_bulk_insert(
+        &self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        let mut new_partitions = Partitions::new();
+        Self::insert_rows(&mut new_partitions, entities, "clean_table_and_bulk_insert")?;
+
+        let mut partitions = self.partitions.lock().await;
+        *partitions = new_partitions;
+
+        Ok(())
+    }
+
+    async fn clean_partition_and_bulk_insert(
+        &self,
+        partition_key: &str,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        let mut new_partitions = Partitions::new();
+        Self::insert_rows(
+            &mut new_partitions,
+            entities,
+            "clean_partition_and_bulk_insert",
+        )?;
+
+        let mut partitions = self.partitions.lock().await;
+        partitions.remove(partition_key);
+
+        for (partition_key, rows) in new_partitions {
+            partitions.entry(partition_key).or_default().extend(rows);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemoryDataWriter;
+    use crate::{test_fixtures::TestEntity, DataWriterError, MyNoSqlDataWriterTrait};
+
+    #[tokio::test]
+    async fn test_insert_existing_entity() {
+        let writer: InMemoryDataWriter<TestEntity> = InMemoryDataWriter::new();
+
+        writer
+            .insert_entity(&TestEntity::new("pk", "rk", 1))
+            .await
+            .unwrap();
+
+        let result = writer.insert_entity(&TestEntity::new("pk", "rk", 2)).await;
+
+        assert!(matches!(
+            result,
+            Err(DataWriterError::RecordAlreadyExists { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_clean_partition_keeps_other_partitions() {
+        let writer: InMemoryDataWriter<TestEntity> = InMemoryDataWriter::new();
+
+        writer
+            .bulk_insert_or_replace(&[
+                TestEntity::new("pk1", "rk1", 1),
+                TestEntity::new("pk1", "rk2", 2),
+                TestEntity::new("pk2", "rk1", 3),
+            ])
+            .await
+            .unwrap();
+
+        writer
+            .clean_partition_and_bulk_insert("pk1", &[TestEntity::new("pk1", "rk3", 4)])
+            .await
+            .unwrap();
+
+        let pk1 = writer.get_by_partition_key("pk1", None).await.unwrap();
+        let pk1 = pk1.unwrap();
+        assert_eq!(pk1.len(), 1);
+        assert_eq!(pk1[0].row_key, "rk3");
+
+        let by_row_key = writer.get_by_row_key("rk1").await.unwrap().unwrap();
+        assert_eq!(by_row_key.len(), 1);
+        assert_eq!(by_row_key[0].value, 3);
+
+        let deleted = writer.delete_row("pk2", "rk1").await.unwrap().unwrap();
+        assert_eq!(deleted.value, 3);
+        assert_eq!(writer.get_rows_amount().await, 1);
+
+        assert!(writer.get_by_row_key("rk1").await.unwrap().is_none());
+    }
+}
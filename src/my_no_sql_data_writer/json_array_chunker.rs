@@ -0,0 +1,88 @@
+use std::ops::Range;
+
+// Packs already serialized json values into json arrays limited by amount and size.
+// Value which alone is bigger than max_body_size goes as a separate array
+pub(crate) struct JsonArrayChunker {
+    max_amount: usize,
+    max_body_size: usize,
+    chunks: Vec<(Range<usize>, Vec<u8>)>,
+    body: Vec<u8>,
+    start: usize,
+    index: usize,
+}
+
+impl JsonArrayChunker {
+    pub fn new(max_amount: usize, max_body_size: usize) -> Self {
+        Self {
+            max_amount,
+            max_body_size,
+            chunks: Vec::new(),
+            body: vec![b'['],
+            start: 0,
+            index: 0,
+        }
+    }
+
+    pub fn push(&mut self, value_json: &[u8]) {
+        let amount = self.index - self.start;
+
+        // Comma and closing bracket are counted as well
+        if amount > 0
+            && (amount >= self.max_amount
+                || self.body.len() + value_json.len() + 2 > self.max_body_size)
+        {
+            self.body.push(b']');
+            let body = std::mem::replace(&mut self.body, vec![b'[']);
+            self.chunks.push((self.start..self.index, body));
+            self.start = self.index;
+        }
+
+        if self.index > self.start {
+            self.body.push(b',');
+        }
+
+        self.body.extend_from_slice(value_json);
+        self.index += 1;
+    }
+
+    // Every chunk comes with the range of the values it contains
+    pub fn finish(mut self) -> Vec<(Range<usize>, Vec<u8>)> {
+        if self.start < self.index {
+            self.body.push(b']');
+            self.chunks.push((self.start..self.index, self.body));
+        }
+
+        self.chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonArrayChunker;
+
+    #[test]
+    fn test_values_are_packed_by_size() {
+        let mut chunker = JsonArrayChunker::new(usize::MAX, 10);
+
+        for value in [&b"1"[..], b"22", b"333", b"4444444444444", b"5"] {
+            chunker.push(value);
+        }
+
+        let chunks: Vec<_> = chunker
+            .finish()
+            .into_iter()
+            .map(|(range, body)| (range, String::from_utf8(body).unwrap()))
+            .collect();
+
+        assert_eq!(
+            chunks,
+            vec![
+                (0..3, "[1,22,333]".to_string()),
+                (3..4, "[4444444444444]".to_string()),
+                (4..5, "[5]".to_string()),
+            ]
+        );
+
+        assert!(JsonArrayChunker::new(1, 10).finish().is_empty());
+    }
+}
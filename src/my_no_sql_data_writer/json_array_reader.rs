@@ -0,0 +1,261 @@
+use std::marker::PhantomData;
+
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+
+use super::{error::IntoDataWriterError, DataWriterError, ErrorContext};
+
+enum ReaderState {
+    ArrayStart,
+    FirstItem,
+    NextItem,
+    Item,
+    Done,
+}
+
+enum ReadResult<TEntity> {
+    Entity(TEntity),
+    NeedMoreData,
+    Done,
+}
+
+// Parses elements of a json array one by one while the body is received in chunks.
+// Only the part of the body which is not parsed yet is kept in memory
+struct JsonArrayReader<TEntity: DeserializeOwned> {
+    buffer: Vec<u8>,
+    position: usize,
+    body_is_complete: bool,
+    state: ReaderState,
+    itm: PhantomData<TEntity>,
+}
+
+impl<TEntity: DeserializeOwned> JsonArrayReader<TEntity> {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            position: 0,
+            body_is_complete: false,
+            state: ReaderState::ArrayStart,
+            itm: PhantomData,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    fn finish(&mut self) {
+        self.body_is_complete = true;
+    }
+
+    // Returns the next significant byte without consuming it. None - more data is needed
+    fn peek_byte(&mut self) -> Result<Option<u8>, String> {
+        while let Some(b) = self.buffer.get(self.position) {
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(*b));
+            }
+
+            self.position += 1;
+        }
+
+        if self.body_is_complete {
+            return Err("Unexpected end of body".to_string());
+        }
+
+        Ok(None)
+    }
+
+    fn read_next(&mut self) -> Result<ReadResult<TEntity>, String> {
+        loop {
+            let b = match self.state {
+                ReaderState::Done => return Ok(ReadResult::Done),
+                _ => match self.peek_byte()? {
+                    Some(b) => b,
+                    None => return Ok(ReadResult::NeedMoreData),
+                },
+            };
+
+            match self.state {
+                ReaderState::ArrayStart => {
+                    self.expect_byte(b, b'[')?;
+                    self.state = ReaderState::FirstItem;
+                }
+                ReaderState::FirstItem | ReaderState::NextItem if b == b']' => {
+                    self.position += 1;
+                    self.state = ReaderState::Done;
+                }
+                ReaderState::FirstItem => self.state = ReaderState::Item,
+                ReaderState::NextItem => {
+                    self.expect_byte(b, b',')?;
+                    self.state = ReaderState::Item;
+                }
+                ReaderState::Item => return self.read_item(),
+                ReaderState::Done => return Ok(ReadResult::Done),
+            }
+        }
+    }
+
+    fn expect_byte(&mut self, b: u8, expected: u8) -> Result<(), String> {
+        if b != expected {
+            return Err(format!(
+                "Expected '{}'. Found '{}'",
+                expected as char, b as char
+            ));
+        }
+
+        self.position += 1;
+        Ok(())
+    }
+
+    fn read_item(&mut self) -> Result<ReadResult<TEntity>, String> {
+        let src = &self.buffer[self.position..];
+        let mut items = serde_json::Deserializer::from_slice(src).into_iter::<TEntity>();
+
+        match items.next() {
+            Some(Ok(entity)) => {
+                let end = items.byte_offset();
+
+                // Number or literal at the end of the chunk may continue in the next one
+                if !self.body_is_complete
+                    && end == src.len()
+                    && !matches!(src[end - 1], b'}' | b']' | b'"')
+                {
+                    return Ok(ReadResult::NeedMoreData);
+                }
+
+                self.position += end;
+                self.state = ReaderState::NextItem;
+                Ok(ReadResult::Entity(entity))
+            }
+            Some(Err(err)) if err.is_eof() && !self.body_is_complete => {
+                Ok(ReadResult::NeedMoreData)
+            }
+            Some(Err(err)) => Err(format!("{:?}", err)),
+            None => Err("Unexpected end of body".to_string()),
+        }
+    }
+}
+
+// Entities are yielded as soon as they are received. Error ends the stream
+pub(crate) fn read_json_array<TEntity, TChunk, TErr>(
+    chunks: impl Stream<Item = Result<TChunk, TErr>>,
+    ctx: ErrorContext,
+) -> impl Stream<Item = Result<TEntity, DataWriterError>>
+where
+    TEntity: DeserializeOwned,
+    TChunk: AsRef<[u8]>,
+    TErr: IntoDataWriterError,
+{
+    let reader = JsonArrayReader::new();
+
+    futures::stream::unfold(Some((reader, Box::pin(chunks), ctx)), |state| async move {
+        let (mut reader, mut chunks, ctx) = state?;
+
+        loop {
+            match reader.read_next() {
+                Ok(ReadResult::Entity(entity)) => {
+                    return Some((Ok(entity), Some((reader, chunks, ctx))))
+                }
+                Ok(ReadResult::Done) => return None,
+                Ok(ReadResult::NeedMoreData) => {}
+                Err(message) => {
+                    let err = DataWriterError::Error {
+                        ctx,
+                        message: format!("Failed to deserialize entity: {}", message),
+                    };
+
+                    return Some((Err(err), None));
+                }
+            }
+
+            match chunks.next().await {
+                Some(Ok(chunk)) => reader.push(chunk.as_ref()),
+                Some(Err(err)) => return Some((Err(err.into_data_writer_error(ctx)), None)),
+                None => reader.finish(),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use serde::Deserialize;
+
+    use crate::{DataWriterError, ErrorContext};
+
+    #[derive(Debug, Deserialize)]
+    struct TestEntity {
+        value: i32,
+    }
+
+    async fn read_chunks(chunks: Vec<&str>) -> Vec<Result<TestEntity, DataWriterError>> {
+        let chunks = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, hyper::Error>(chunk.as_bytes().to_vec())),
+        );
+
+        super::read_json_array(chunks, ErrorContext::default())
+            .collect()
+            .await
+    }
+
+    async fn read(src: &str) -> Vec<Result<TestEntity, DataWriterError>> {
+        read_chunks(vec![src]).await
+    }
+
+    #[tokio::test]
+    async fn test_array_is_read_item_by_item() {
+        let src = r#" [ {"value": 1}, {"value": 2 , "other": [1, 2]},{"value":3} ] "#;
+
+        let values: Vec<i32> = read(src)
+            .await
+            .into_iter()
+            .map(|item| item.unwrap().value)
+            .collect();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        assert!(read("[]").await.is_empty());
+        assert!(read(" [\n] ").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_array_split_at_any_byte_is_read() {
+        let src = r#"[{"value": 1}, {"value": 22, "other": "a,]}"},{"value":333}]"#;
+
+        for split in 0..=src.len() {
+            let (left, right) = src.split_at(split);
+
+            let values: Vec<i32> = read_chunks(vec![left, right])
+                .await
+                .into_iter()
+                .map(|item| item.unwrap().value)
+                .collect();
+
+            assert_eq!(values, vec![1, 22, 333], "Split at {}", split);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broken_array_ends_with_error() {
+        let result = read(r#"[{"value": 1} {"value": 2}]"#).await;
+        assert_eq!(result.len(), 2);
+        assert!(result[0].is_ok());
+        assert!(matches!(result[1], Err(DataWriterError::Error { .. })));
+
+        let result = read_chunks(vec![r#"[{"value": 1},"#, r#"{"value""#]).await;
+        assert_eq!(result.len(), 2);
+        assert!(result[1].is_err());
+
+        let result = read(r#"{"value": 1}"#).await;
+        assert_eq!(result.len(), 1);
+        assert!(result[0].is_err());
+
+        let result = read("").await;
+        assert_eq!(result.len(), 1);
+        assert!(result[0].is_err());
+    }
+}
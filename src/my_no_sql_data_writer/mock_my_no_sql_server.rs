@@ -0,0 +1,1026 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    ops::Bound,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+use serde_json::Value;
+use tokio::sync::{oneshot, watch};
+
+use super::{transaction::TransactionStep, MyNoSqlWriterSettings, OperationFailHttpContract};
+
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn get_query_param(&self, name: &str) -> Option<&str> {
+        get_query_param(&self.query, name)
+    }
+}
+
+struct MockRow {
+    version: i64,
+    entity: Value,
+}
+
+// partition_key -> row_key -> row
+type MockTable = BTreeMap<String, BTreeMap<String, MockRow>>;
+
+/// Scripted failure the mock server answers with instead of handling a request.
+#[derive(Debug, Clone)]
+pub enum MockFault {
+    // Response is delayed and then the request is handled as usual
+    Delay(Duration),
+    // Request is handled as usual, but the response goes with an empty body
+    EmptyBody,
+    DropConnection,
+    ServerError { status: u16, body: String },
+    MalformedJson { status: u16 },
+    // Request is rejected with an OperationFailHttpContract the way the real server rejects it.
+    // Example: reason "TableNotFound" with status 400
+    Reject { status: u16, reason: String },
+    // OperationFailHttpContract with a reason the writer does not know
+    UnknownReason { status: u16, reason: String },
+    // Request is handled as usual, but only the first `after` bytes of the body are sent
+    // until `resume_paused_bodies` is called
+    PauseBody { after: usize },
+}
+
+#[derive(Default)]
+struct MockServerData {
+    tables: HashMap<String, MockTable>,
+    requests: Vec<MockRequest>,
+    // Faults are taken in order. Fault without a path applies to any request
+    faults: VecDeque<(Option<String>, MockFault)>,
+    last_version: i64,
+    transactions: HashMap<String, Vec<TransactionStep>>,
+    last_transaction_id: u64,
+}
+
+struct MockResponse {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    fn ok() -> Self {
+        Self {
+            status: 200,
+            body: Vec::new(),
+        }
+    }
+
+    fn json(value: &Value) -> Self {
+        Self {
+            status: 200,
+            body: serde_json::to_vec(value).unwrap(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self {
+            status: 404,
+            body: Vec::new(),
+        }
+    }
+
+    fn fail(status: u16, reason: &str, message: String) -> Self {
+        let contract = OperationFailHttpContract {
+            reason: reason.to_string(),
+            message,
+        };
+
+        Self {
+            status,
+            body: serde_json::to_vec(&contract).unwrap(),
+        }
+    }
+
+    fn into_hyper_response(self) -> Response<Body> {
+        Response::builder()
+            .status(self.status)
+            .header("Content-Type", "application/json")
+            .body(Body::from(self.body))
+            .unwrap()
+    }
+
+    fn into_paused_hyper_response(
+        self,
+        after: usize,
+        mut resume_receiver: watch::Receiver<bool>,
+    ) -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        let mut head = self.body;
+        let tail = head.split_off(after.min(head.len()));
+
+        tokio::spawn(async move {
+            if sender.send_data(head.into()).await.is_err() {
+                return;
+            }
+
+            while !*resume_receiver.borrow() {
+                if resume_receiver.changed().await.is_err() {
+                    return;
+                }
+            }
+
+            let _ = sender.send_data(tail.into()).await;
+        });
+
+        Response::builder()
+            .status(self.status)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .unwrap()
+    }
+}
+
+/// MyNoSql server look-alike listening on a random localhost port.
+/// Implements the Row, Rows, Bulk and Tables endpoints the writer calls and answers
+/// with `OperationFailHttpContract` bodies the same way the real server does.
+///
+/// Every written row gets a new version which is put into the `TimeStamp` field as a number,
+/// so `Row/Replace` checks the `timeStamp` query parameter against it.
+pub struct MockMyNoSqlServer {
+    addr: SocketAddr,
+    data: Arc<Mutex<MockServerData>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    resume_bodies: watch::Sender<bool>,
+}
+
+impl MockMyNoSqlServer {
+    pub async fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let data = Arc::new(Mutex::new(MockServerData::default()));
+
+        let (resume_bodies, resume_receiver) = watch::channel(false);
+
+        let data_to_serve = data.clone();
+        let make_service = make_service_fn(move |_| {
+            let data = data_to_serve.clone();
+            let resume_receiver = resume_receiver.clone();
+            let service =
+                service_fn(move |req| handle_request(data.clone(), resume_receiver.clone(), req));
+            async move { Ok::<_, Infallible>(service) }
+        });
+
+        let (shutdown, shutdown_receiver) = oneshot::channel::<()>();
+
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = shutdown_receiver.await;
+            });
+
+        tokio::spawn(server);
+
+        Self {
+            addr,
+            data,
+            shutdown: Some(shutdown),
+            resume_bodies,
+        }
+    }
+
+    pub fn get_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn get_settings(&self) -> Arc<MockMyNoSqlServerSettings> {
+        Arc::new(MockMyNoSqlServerSettings {
+            url: self.get_url(),
+        })
+    }
+
+    pub fn create_table(&self, table_name: &str) {
+        let mut data = self.data.lock().unwrap();
+        data.tables.entry(table_name.to_string()).or_default();
+    }
+
+    pub fn get_rows_amount(&self, table_name: &str) -> usize {
+        let data = self.data.lock().unwrap();
+        match data.tables.get(table_name) {
+            Some(table) => table.values().map(|rows| rows.len()).sum(),
+            None => 0,
+        }
+    }
+
+    pub fn get_requests(&self) -> Vec<MockRequest> {
+        let data = self.data.lock().unwrap();
+        data.requests.clone()
+    }
+
+    pub fn inject_fault(&self, fault: MockFault) {
+        let mut data = self.data.lock().unwrap();
+        data.faults.push_back((None, fault));
+    }
+
+    /// Fault applies to the next request to the path. Example: `/Row/Insert`
+    pub fn inject_fault_for_path(&self, path: &str, fault: MockFault) {
+        let mut data = self.data.lock().unwrap();
+        data.faults.push_back((Some(path.to_string()), fault));
+    }
+
+    /// Sends the rest of the bodies paused by `MockFault::PauseBody`
+    pub fn resume_paused_bodies(&self) {
+        let _ = self.resume_bodies.send(true);
+    }
+
+    /// Same fault for the next `amount` requests. Handy to emulate a 5xx burst during a restart
+    pub fn inject_faults(&self, fault: MockFault, amount: usize) {
+        let mut data = self.data.lock().unwrap();
+        for _ in 0..amount {
+            data.faults.push_back((None, fault.clone()));
+        }
+    }
+}
+
+impl Drop for MockMyNoSqlServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+pub struct MockMyNoSqlServerSettings {
+    url: String,
+}
+
+#[async_trait::async_trait]
+impl MyNoSqlWriterSettings for MockMyNoSqlServerSettings {
+    async fn get_url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+// Returning an error from the service makes hyper close the connection without a response
+async fn handle_request(
+    data: Arc<Mutex<MockServerData>>,
+    resume_receiver: watch::Receiver<bool>,
+    req: Request<Body>,
+) -> Result<Response<Body>, std::io::Error> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let query = parse_query(req.uri().query().unwrap_or(""));
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body.to_vec(),
+        Err(err) => {
+            let response =
+                MockResponse::fail(400, "InvalidBody", format!("Can not read body: {}", err));
+            return Ok(response.into_hyper_response());
+        }
+    };
+
+    let fault = {
+        let mut data = data.lock().unwrap();
+
+        data.requests.push(MockRequest {
+            method: method.to_string(),
+            path: path.clone(),
+            query: query.clone(),
+            body: body.clone(),
+        });
+
+        data.take_fault(path.as_str())
+    };
+
+    let mut empty_body = false;
+    let mut pause_body_after = None;
+
+    if let Some(fault) = fault {
+        match fault {
+            MockFault::Delay(delay) => tokio::time::sleep(delay).await,
+            MockFault::EmptyBody => empty_body = true,
+            MockFault::PauseBody { after } => pause_body_after = Some(after),
+            MockFault::DropConnection => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "Injected fault: connection is dropped",
+                ));
+            }
+            MockFault::ServerError { status, body } => {
+                let response = MockResponse {
+                    status,
+                    body: body.into_bytes(),
+                };
+                return Ok(response.into_hyper_response());
+            }
+            MockFault::MalformedJson { status } => {
+                let response = MockResponse {
+                    status,
+                    body: br#"{"reason":"TableNotFound","mess"#.to_vec(),
+                };
+                return Ok(response.into_hyper_response());
+            }
+            MockFault::Reject { status, reason } => {
+                let response = MockResponse::fail(status, reason.as_str(), "Rejected".to_string());
+                return Ok(response.into_hyper_response());
+            }
+            MockFault::UnknownReason { status, reason } => {
+                let response = MockResponse::fail(status, reason.as_str(), "Injected".to_string());
+                return Ok(response.into_hyper_response());
+            }
+        }
+    }
+
+    let mut response = data
+        .lock()
+        .unwrap()
+        .handle(&method, path.as_str(), &query, &body);
+
+    if empty_body {
+        response.body.clear();
+    }
+
+    match pause_body_after {
+        Some(after) => Ok(response.into_paused_hyper_response(after, resume_receiver)),
+        None => Ok(response.into_hyper_response()),
+    }
+}
+
+impl MockServerData {
+    fn take_fault(&mut self, path: &str) -> Option<MockFault> {
+        let index = self
+            .faults
+            .iter()
+            .position(|(fault_path, _)| match fault_path {
+                Some(fault_path) => fault_path == path,
+                None => true,
+            })?;
+
+        self.faults.remove(index).map(|(_, fault)| fault)
+    }
+
+    fn handle(
+        &mut self,
+        method: &Method,
+        path: &str,
+        query: &[(String, String)],
+        body: &[u8],
+    ) -> MockResponse {
+        if path == "/Tables/Create" || path == "/Tables/CreateIfNotExists" {
+            return self.create_table(query, path == "/Tables/CreateIfNotExists");
+        }
+
+        if let Some(action) = path.strip_prefix("/Transactions/") {
+            return self.handle_transaction(action, query, body);
+        }
+
+        let table_name = match get_query_param(query, "tableName") {
+            Some(table_name) => table_name.to_string(),
+            None => {
+                return MockResponse::fail(
+                    400,
+                    "RequiredQueryParameterIsMissing",
+                    "tableName".to_string(),
+                )
+            }
+        };
+
+        self.last_version += 1;
+        let version = self.last_version;
+
+        let table = match self.tables.get_mut(table_name.as_str()) {
+            Some(table) => table,
+            None => return MockResponse::fail(400, "TableNotFound", table_name),
+        };
+
+        match (method, path) {
+            (&Method::GET, "/Row") => get_rows(table, query),
+            (&Method::DELETE, "/Row") => delete_row(table, query),
+            (&Method::POST, "/Row/Insert") => insert_row(table, body, version, false),
+            (&Method::POST, "/Row/InsertOrReplace") => insert_row(table, body, version, true),
+            (&Method::PUT, "/Row/Replace") => replace_row(table, query, body, version),
+            (&Method::DELETE, "/Rows") => {
+                for partition_key in get_query_params(query, "partitionKey") {
+                    table.remove(partition_key);
+                }
+                MockResponse::ok()
+            }
+            (&Method::POST, "/Rows/SinglePartitionMultipleRows") => {
+                get_partition_rows(table, query, body)
+            }
+            (&Method::GET, "/Rows/HighestRowAndBelow") => get_highest_row_and_below(table, query),
+            (&Method::POST, "/Bulk/InsertOrReplace") => bulk_insert(table, body, version),
+            (&Method::POST, "/Bulk/Delete") => bulk_delete(table, body),
+            (&Method::POST, "/Bulk/CleanAndBulkInsert") => {
+                match get_query_param(query, "partitionKey") {
+                    Some(partition_key) => {
+                        table.remove(partition_key);
+                    }
+                    None => table.clear(),
+                }
+
+                bulk_insert(table, body, version)
+            }
+            _ => MockResponse::not_found(),
+        }
+    }
+
+    fn handle_transaction(
+        &mut self,
+        action: &str,
+        query: &[(String, String)],
+        body: &[u8],
+    ) -> MockResponse {
+        if action == "Start" {
+            self.last_transaction_id += 1;
+            let transaction_id = self.last_transaction_id.to_string();
+            self.transactions.insert(transaction_id.clone(), Vec::new());
+
+            return MockResponse::json(&serde_json::json!({ "transactionId": transaction_id }));
+        }
+
+        let transaction_id = get_query_param(query, "transactionId").unwrap_or_default();
+
+        if !self.transactions.contains_key(transaction_id) {
+            return MockResponse::fail(400, "TransactionNotFound", transaction_id.to_string());
+        }
+
+        match action {
+            "Append" => {
+                let steps = match serde_json::from_slice::<Vec<TransactionStep>>(body) {
+                    Ok(steps) => steps,
+                    Err(err) => {
+                        return MockResponse::fail(400, "JsonParseFail", format!("{}", err))
+                    }
+                };
+
+                if let Some(transaction) = self.transactions.get_mut(transaction_id) {
+                    transaction.extend(steps);
+                }
+
+                MockResponse::ok()
+            }
+            "Commit" => {
+                let steps = self.transactions.remove(transaction_id).unwrap_or_default();
+                self.commit_transaction(steps)
+            }
+            "Cancel" => {
+                self.transactions.remove(transaction_id);
+                MockResponse::ok()
+            }
+            _ => MockResponse::not_found(),
+        }
+    }
+
+    // Everything is validated before the first change, so a failed commit changes nothing
+    fn commit_transaction(&mut self, steps: Vec<TransactionStep>) -> MockResponse {
+        self.last_version += 1;
+        let version = self.last_version;
+
+        let mut prepared_steps = Vec::with_capacity(steps.len());
+
+        for step in steps {
+            if !self.tables.contains_key(step.get_table_name()) {
+                let table_name = step.get_table_name().to_string();
+                return MockResponse::fail(400, "TableNotFound", table_name);
+            }
+
+            let mut rows = Vec::new();
+
+            if let TransactionStep::InsertOrReplaceEntities { entities, .. } = &step {
+                for entity in entities {
+                    match get_keys(entity.clone(), version) {
+                        Ok(row) => rows.push(row),
+                        Err(response) => return response,
+                    }
+                }
+            }
+
+            prepared_steps.push((step, rows));
+        }
+
+        for (step, rows) in prepared_steps {
+            let table = self.tables.get_mut(step.get_table_name()).unwrap();
+
+            match step {
+                TransactionStep::CleanTable { .. } => table.clear(),
+                TransactionStep::DeletePartitions { partition_keys, .. } => {
+                    for partition_key in partition_keys {
+                        table.remove(partition_key.as_str());
+                    }
+                }
+                TransactionStep::DeleteRows {
+                    partition_key,
+                    row_keys,
+                    ..
+                } => {
+                    if let Some(partition) = table.get_mut(partition_key.as_str()) {
+                        for row_key in row_keys {
+                            partition.remove(row_key.as_str());
+                        }
+
+                        if partition.is_empty() {
+                            table.remove(partition_key.as_str());
+                        }
+                    }
+                }
+                TransactionStep::InsertOrReplaceEntities { .. } => {
+                    for (partition_key, row_key, entity) in rows {
+                        table
+                            .entry(partition_key)
+                            .or_default()
+                            .insert(row_key, MockRow { version, entity });
+                    }
+                }
+            }
+        }
+
+        MockResponse::ok()
+    }
+
+    fn create_table(&mut self, query: &[(String, String)], if_not_exists: bool) -> MockResponse {
+        let table_name = match get_query_param(query, "tableName") {
+            Some(table_name) => table_name.to_string(),
+            None => {
+                return MockResponse::fail(
+                    400,
+                    "RequiredQueryParameterIsMissing",
+                    "tableName".to_string(),
+                )
+            }
+        };
+
+        if self.tables.contains_key(table_name.as_str()) {
+            if if_not_exists {
+                return MockResponse::ok();
+            }
+
+            return MockResponse::fail(409, "TableAlreadyExists", table_name);
+        }
+
+        self.tables.insert(table_name, MockTable::new());
+        MockResponse::ok()
+    }
+}
+
+fn get_rows(table: &MockTable, query: &[(String, String)]) -> MockResponse {
+    let partition_key = get_query_param(query, "partitionKey");
+    let row_key = get_query_param(query, "rowKey");
+
+    let skip = get_query_param(query, "skip")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let limit = get_query_param(query, "limit")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(usize::MAX);
+
+    match (partition_key, row_key) {
+        (Some(partition_key), Some(row_key)) => {
+            match table.get(partition_key).and_then(|rows| rows.get(row_key)) {
+                Some(row) => MockResponse::json(&row.entity),
+                None => MockResponse::not_found(),
+            }
+        }
+        (Some(partition_key), None) => match table.get(partition_key) {
+            Some(rows) => rows_to_response(rows.values().skip(skip).take(limit)),
+            None => MockResponse::not_found(),
+        },
+        (None, Some(row_key)) => {
+            rows_to_response(table.values().filter_map(|rows| rows.get(row_key)))
+        }
+        (None, None) => rows_to_response(
+            table
+                .values()
+                .flat_map(|rows| rows.values())
+                .skip(skip)
+                .take(limit),
+        ),
+    }
+}
+
+// Body is an array of row keys. Absent rows are skipped
+fn get_partition_rows(table: &MockTable, query: &[(String, String)], body: &[u8]) -> MockResponse {
+    let row_keys = match serde_json::from_slice::<Vec<String>>(body) {
+        Ok(row_keys) => row_keys,
+        Err(err) => return MockResponse::fail(400, "JsonParseFail", format!("{}", err)),
+    };
+
+    let rows = match get_query_param(query, "partitionKey").and_then(|pk| table.get(pk)) {
+        Some(rows) => rows,
+        None => return MockResponse::not_found(),
+    };
+
+    rows_to_response(
+        row_keys
+            .iter()
+            .filter_map(|row_key| rows.get(row_key.as_str())),
+    )
+}
+
+fn get_highest_row_and_below(table: &MockTable, query: &[(String, String)]) -> MockResponse {
+    let (partition_key, row_key) = match (
+        get_query_param(query, "partitionKey"),
+        get_query_param(query, "rowKey"),
+    ) {
+        (Some(partition_key), Some(row_key)) => (partition_key, row_key),
+        _ => {
+            return MockResponse::fail(
+                400,
+                "RequiredQueryParameterIsMissing",
+                "partitionKey and rowKey".to_string(),
+            )
+        }
+    };
+
+    let max_amount = get_query_param(query, "maxAmount")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(usize::MAX);
+
+    match table.get(partition_key) {
+        Some(rows) => rows_to_response(
+            rows.range::<str, _>((Bound::Unbounded, Bound::Included(row_key)))
+                .rev()
+                .take(max_amount)
+                .map(|(_, row)| row),
+        ),
+        None => MockResponse::not_found(),
+    }
+}
+
+fn delete_row(table: &mut MockTable, query: &[(String, String)]) -> MockResponse {
+    let (partition_key, row_key) = match (
+        get_query_param(query, "partitionKey"),
+        get_query_param(query, "rowKey"),
+    ) {
+        (Some(partition_key), Some(row_key)) => (partition_key, row_key),
+        _ => {
+            return MockResponse::fail(
+                400,
+                "RequiredQueryParameterIsMissing",
+                "partitionKey and rowKey".to_string(),
+            )
+        }
+    };
+
+    let rows = match table.get_mut(partition_key) {
+        Some(rows) => rows,
+        None => return MockResponse::not_found(),
+    };
+
+    let removed_row = rows.remove(row_key);
+
+    if rows.is_empty() {
+        table.remove(partition_key);
+    }
+
+    match removed_row {
+        Some(row) => MockResponse::json(&row.entity),
+        None => MockResponse::not_found(),
+    }
+}
+
+fn insert_row(table: &mut MockTable, body: &[u8], version: i64, replace: bool) -> MockResponse {
+    let (partition_key, row_key, entity) = match parse_entity(body, version) {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
+
+    let rows = table.entry(partition_key.clone()).or_default();
+
+    if !replace && rows.contains_key(row_key.as_str()) {
+        return MockResponse::fail(
+            409,
+            "RecordAlreadyExists",
+            format!("{}/{}", partition_key, row_key),
+        );
+    }
+
+    rows.insert(row_key, MockRow { version, entity });
+    MockResponse::ok()
+}
+
+fn replace_row(
+    table: &mut MockTable,
+    query: &[(String, String)],
+    body: &[u8],
+    version: i64,
+) -> MockResponse {
+    let (partition_key, row_key, entity) = match parse_entity(body, version) {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
+
+    let row = match table
+        .get_mut(partition_key.as_str())
+        .and_then(|rows| rows.get_mut(row_key.as_str()))
+    {
+        Some(row) => row,
+        None => {
+            return MockResponse::fail(
+                404,
+                "RecordNotFound",
+                format!("{}/{}", partition_key, row_key),
+            )
+        }
+    };
+
+    let expected_version = get_query_param(query, "timeStamp").and_then(|v| v.parse().ok());
+
+    if expected_version != Some(row.version) {
+        return MockResponse::fail(
+            409,
+            "RecordIsChanged",
+            format!("Expected: {:?}. Actual: {}", expected_version, row.version),
+        );
+    }
+
+    *row = MockRow { version, entity };
+    MockResponse::ok()
+}
+
+fn bulk_insert(table: &mut MockTable, body: &[u8], version: i64) -> MockResponse {
+    let entities = match serde_json::from_slice::<Vec<Value>>(body) {
+        Ok(entities) => entities,
+        Err(err) => return MockResponse::fail(400, "JsonParseFail", format!("{}", err)),
+    };
+
+    let mut rows = Vec::with_capacity(entities.len());
+
+    for entity in entities {
+        match get_keys(entity, version) {
+            Ok(row) => rows.push(row),
+            Err(response) => return response,
+        }
+    }
+
+    for (partition_key, row_key, entity) in rows {
+        table
+            .entry(partition_key)
+            .or_default()
+            .insert(row_key, MockRow { version, entity });
+    }
+
+    MockResponse::ok()
+}
+
+// Body is partition_key -> [row_key]. Answers with the rows which were deleted.
+// The writer does not rely on it - see the EmptyBody fault
+fn bulk_delete(table: &mut MockTable, body: &[u8]) -> MockResponse {
+    let keys = match serde_json::from_slice::<BTreeMap<String, Vec<String>>>(body) {
+        Ok(keys) => keys,
+        Err(err) => return MockResponse::fail(400, "JsonParseFail", format!("{}", err)),
+    };
+
+    let mut removed_rows = Vec::new();
+
+    for (partition_key, row_keys) in keys {
+        let rows = match table.get_mut(partition_key.as_str()) {
+            Some(rows) => rows,
+            None => continue,
+        };
+
+        for row_key in row_keys {
+            if let Some(row) = rows.remove(row_key.as_str()) {
+                removed_rows.push(row);
+            }
+        }
+
+        if rows.is_empty() {
+            table.remove(partition_key.as_str());
+        }
+    }
+
+    rows_to_response(removed_rows.iter())
+}
+
+fn rows_to_response<'s>(rows: impl Iterator<Item = &'s MockRow>) -> MockResponse {
+    let entities: Vec<&Value> = rows.map(|row| &row.entity).collect();
+
+    MockResponse {
+        status: 200,
+        body: serde_json::to_vec(&entities).unwrap(),
+    }
+}
+
+fn parse_entity(body: &[u8], version: i64) -> Result<(String, String, Value), MockResponse> {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(entity) => get_keys(entity, version),
+        Err(err) => Err(MockResponse::fail(400, "JsonParseFail", format!("{}", err))),
+    }
+}
+
+fn get_keys(mut entity: Value, version: i64) -> Result<(String, String, Value), MockResponse> {
+    let partition_key = entity.get("PartitionKey").and_then(|v| v.as_str());
+    let row_key = entity.get("RowKey").and_then(|v| v.as_str());
+
+    let (partition_key, row_key) = match (partition_key, row_key) {
+        (Some(partition_key), Some(row_key)) => (partition_key.to_string(), row_key.to_string()),
+        _ => {
+            return Err(MockResponse::fail(
+                400,
+                "RequiredEntityFieldIsMissing",
+                "PartitionKey and RowKey are required".to_string(),
+            ))
+        }
+    };
+
+    if let Some(object) = entity.as_object_mut() {
+        object.insert("TimeStamp".to_string(), Value::from(version));
+    }
+
+    Ok((partition_key, row_key, entity))
+}
+
+fn get_query_param<'s>(query: &'s [(String, String)], name: &str) -> Option<&'s str> {
+    query
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn get_query_params<'s>(query: &'s [(String, String)], name: &str) -> Vec<&'s str> {
+    query
+        .iter()
+        .filter(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    for pair in query.split('&') {
+        if pair.is_empty() {
+            continue;
+        }
+
+        let mut parts = pair.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("");
+
+        result.push((decode_query_value(key), decode_query_value(value)));
+    }
+
+    result
+}
+
+fn decode_query_value(src: &str) -> String {
+    let src = src.as_bytes();
+    let mut result = Vec::with_capacity(src.len());
+    let mut i = 0;
+
+    while i < src.len() {
+        match src[i] {
+            b'+' => result.push(b' '),
+            b'%' if i + 2 < src.len() => {
+                let hex = std::str::from_utf8(&src[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        result.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => result.push(b'%'),
+                }
+            }
+            byte => result.push(byte),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&result).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, Client, Method, Request};
+    use serde_json::Value;
+
+    use super::{MockFault, MockMyNoSqlServer};
+    use crate::OperationFailHttpContract;
+
+    async fn send(
+        server: &MockMyNoSqlServer,
+        method: Method,
+        path_and_query: &str,
+        body: &str,
+    ) -> (u16, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", server.get_url(), path_and_query))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = Client::new().request(request).await.unwrap();
+        let status = response.status().as_u16();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, body.to_vec())
+    }
+
+    fn get_reason(body: &[u8]) -> String {
+        serde_json::from_slice::<OperationFailHttpContract>(body)
+            .unwrap()
+            .reason
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = super::parse_query("tableName=test&partitionKey=a%2Fb&rowKey=c+d");
+
+        assert_eq!(super::get_query_param(&query, "tableName"), Some("test"));
+        assert_eq!(super::get_query_param(&query, "partitionKey"), Some("a/b"));
+        assert_eq!(super::get_query_param(&query, "rowKey"), Some("c d"));
+    }
+
+    #[tokio::test]
+    async fn test_rows_are_versioned_and_replace_checks_version() {
+        let server = MockMyNoSqlServer::start().await;
+
+        let entity = r#"{"PartitionKey":"pk","RowKey":"rk","Value":1}"#;
+
+        let (status, body) =
+            send(&server, Method::POST, "/Row/Insert?tableName=test", entity).await;
+        assert_eq!(status, 400);
+        assert_eq!(get_reason(&body), "TableNotFound");
+
+        server.create_table("test");
+
+        let (status, _) = send(&server, Method::POST, "/Row/Insert?tableName=test", entity).await;
+        assert_eq!(status, 200);
+
+        let (status, body) =
+            send(&server, Method::POST, "/Row/Insert?tableName=test", entity).await;
+        assert_eq!(status, 409);
+        assert_eq!(get_reason(&body), "RecordAlreadyExists");
+
+        let (status, body) = send(
+            &server,
+            Method::GET,
+            "/Row?tableName=test&partitionKey=pk&rowKey=rk",
+            "",
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let row: Value = serde_json::from_slice(&body).unwrap();
+        let version = row["TimeStamp"].as_i64().unwrap();
+
+        let path = format!("/Row/Replace?tableName=test&timeStamp={}", version + 1);
+        let (status, body) = send(&server, Method::PUT, &path, entity).await;
+        assert_eq!(status, 409);
+        assert_eq!(get_reason(&body), "RecordIsChanged");
+
+        let path = format!("/Row/Replace?tableName=test&timeStamp={}", version);
+        let (status, _) = send(&server, Method::PUT, &path, entity).await;
+        assert_eq!(status, 200);
+
+        assert_eq!(server.get_rows_amount("test"), 1);
+        assert_eq!(server.get_requests().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_faults_are_taken_in_order_and_by_path() {
+        let server = MockMyNoSqlServer::start().await;
+        server.create_table("test");
+
+        server.inject_fault_for_path(
+            "/Row/Insert",
+            MockFault::Reject {
+                status: 400,
+                reason: "RequiredEntityFieldIsMissing".to_string(),
+            },
+        );
+        server.inject_fault(MockFault::ServerError {
+            status: 503,
+            body: "Restarting".to_string(),
+        });
+
+        let entity = r#"{"PartitionKey":"pk","RowKey":"rk","Value":1}"#;
+
+        // Fault for another path is skipped
+        let (status, body) = send(&server, Method::GET, "/Row?tableName=test", "").await;
+        assert_eq!(status, 503);
+        assert_eq!(body, b"Restarting");
+
+        let (status, body) =
+            send(&server, Method::POST, "/Row/Insert?tableName=test", entity).await;
+        assert_eq!(status, 400);
+        assert_eq!(get_reason(&body), "RequiredEntityFieldIsMissing");
+        assert_eq!(server.get_rows_amount("test"), 0);
+
+        let (status, _) = send(&server, Method::POST, "/Row/Insert?tableName=test", entity).await;
+        assert_eq!(status, 200);
+        assert_eq!(server.get_rows_amount("test"), 1);
+    }
+}
@@ -1,8 +1,37 @@
+mod batching_data_writer;
 mod error;
+#[cfg(any(test, feature = "testing"))]
+mod in_memory_data_writer;
+mod json_array_chunker;
+mod json_array_reader;
+#[cfg(any(test, feature = "testing"))]
+mod mock_my_no_sql_server;
 mod my_no_sql_data_writer;
+mod my_no_sql_data_writer_builder;
+mod my_no_sql_data_writer_trait;
+mod request_executor;
+mod request_limiter;
+mod retry_policy;
 mod settings;
+mod spooling_data_writer;
+#[cfg(test)]
+mod test_fixtures;
+mod transaction;
 mod update_read_statistics;
-pub use error::DataWriterError;
+mod writer_response;
+pub use batching_data_writer::*;
+pub use error::{DataWriterError, ErrorContext, FlUrlErrorSource};
+#[cfg(any(test, feature = "testing"))]
+pub use in_memory_data_writer::*;
+#[cfg(any(test, feature = "testing"))]
+pub use mock_my_no_sql_server::*;
 pub use my_no_sql_data_writer::*;
+pub use my_no_sql_data_writer_builder::*;
+pub use my_no_sql_data_writer_trait::*;
+pub use request_limiter::*;
+pub use retry_policy::*;
 pub use settings::*;
+pub use spooling_data_writer::*;
+pub use transaction::MyNoSqlTransaction;
 pub use update_read_statistics::*;
+pub use writer_response::OperationFailHttpContract;
@@ -1,19 +1,36 @@
-use std::sync::Arc;
-
-use flurl::{FlUrl, FlUrlResponse};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
+
+use flurl::{FlUrl, FlUrlError, FlUrlResponse};
+use futures::{future::Either, Stream, StreamExt};
 use my_logger::LogEventCtx;
 use my_no_sql_server_abstractions::{DataSynchronizationPeriod, MyNoSqlEntity};
 
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::watch;
 
 use crate::MyNoSqlWriterSettings;
 
-use super::{DataWriterError, UpdateReadStatistics};
+use super::{
+    json_array_chunker::JsonArrayChunker, request_executor::RequestExecutor,
+    writer_response::WriterResponse, DataWriterError, ErrorContext, MyNoSqlDataWriterBuilder,
+    MyNoSqlTransaction, RequestLimiter, UpdateReadStatistics,
+};
 
 const ROW_CONTROLLER: &str = "Row";
 const ROWS_CONTROLLER: &str = "Rows";
 const BULK_CONTROLLER: &str = "Bulk";
 
+const ROW_KEY_RANGE_PAGE_SIZE: usize = 1000;
+
+// Room for the type and the table name of a transaction step around the entities
+const TRANSACTION_STEP_OVERHEAD: usize = 1024;
+
 pub struct CreateTableParams {
     pub persist: bool,
     pub max_partitions_amount: Option<usize>,
@@ -44,9 +61,36 @@ impl CreateTableParams {
     }
 }
 
+/// Limits of a single bulk request. Bigger uploads are split into several requests.
+#[derive(Debug, Clone)]
+pub struct BulkChunkParams {
+    pub max_entities_amount: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for BulkChunkParams {
+    fn default() -> Self {
+        Self {
+            max_entities_amount: 5_000,
+            max_body_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TableReadyState {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
 pub struct MyNoSqlDataWriter<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize> {
-    settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>,
+    executor: RequestExecutor,
     sync_period: DataSynchronizationPeriod,
+    default_update_read_statistics: Option<UpdateReadStatistics>,
+    batch_read_concurrency: usize,
+    bulk_chunk_params: Option<BulkChunkParams>,
+    table_ready: watch::Receiver<TableReadyState>,
     itm: Option<TEntity>,
 }
 
@@ -63,118 +107,330 @@ impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
         auto_create_table_params: Option<CreateTableParams>,
         sync_period: DataSynchronizationPeriod,
     ) -> Self {
+        let mut builder = Self::builder(settings).with_sync_period(sync_period);
+
         if let Some(create_table_params) = auto_create_table_params {
-            tokio::spawn(create_table_if_not_exists(
-                settings.clone(),
-                TEntity::TABLE_NAME,
-                create_table_params,
-                sync_period,
-            ));
+            builder = builder.with_auto_create_table(create_table_params);
         }
 
+        builder.build()
+    }
+
+    pub fn builder(
+        settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>,
+    ) -> MyNoSqlDataWriterBuilder<TEntity> {
+        MyNoSqlDataWriterBuilder::new(settings)
+    }
+
+    pub(crate) fn from_builder(builder: MyNoSqlDataWriterBuilder<TEntity>) -> Self {
+        let executor = RequestExecutor {
+            settings: builder.settings,
+            headers: builder.headers,
+            retry_policy: builder.retry_policy,
+            timeout: builder.timeout,
+            request_limiter: builder.request_limiter,
+        };
+
+        let table_ready = match builder.auto_create_table_params {
+            Some(create_table_params) => {
+                let (sender, receiver) = watch::channel(TableReadyState::Pending);
+
+                tokio::spawn(create_table_in_background(
+                    executor.clone(),
+                    TEntity::TABLE_NAME,
+                    create_table_params,
+                    builder.sync_period,
+                    sender,
+                ));
+
+                receiver
+            }
+            None => watch::channel(TableReadyState::Ready).1,
+        };
+
         Self {
-            settings,
+            executor,
             itm: None,
-            sync_period,
+            sync_period: builder.sync_period,
+            default_update_read_statistics: builder.default_update_read_statistics,
+            batch_read_concurrency: builder.batch_read_concurrency,
+            bulk_chunk_params: builder.bulk_chunk_params,
+            table_ready,
         }
     }
 
-    async fn get_fl_url(&self) -> FlUrl {
-        let url = self.settings.get_url().await;
-        FlUrl::new(url)
+    pub fn get_request_limiter(&self) -> Option<&RequestLimiter> {
+        self.executor.request_limiter.as_ref()
     }
 
-    pub async fn create_table(&self, params: CreateTableParams) -> Result<(), DataWriterError> {
-        let url = self.settings.get_url().await;
-        let fl_url = FlUrl::new(url.as_str());
+    pub fn get_table_ready_state(&self) -> TableReadyState {
+        self.table_ready.borrow().clone()
+    }
 
-        let fl_url = fl_url
-            .append_path_segment("Tables")
-            .append_path_segment("Create")
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .append_data_sync_period(&self.sync_period);
+    /// Resolves once the table auto-creation requested on construction is done.
+    /// Transient failures are retried in the background for as long as it takes,
+    /// so wrap it into a timeout if the server may be unreachable.
+    pub async fn wait_until_ready(&self) -> Result<(), DataWriterError> {
+        let mut table_ready = self.table_ready.clone();
+
+        loop {
+            let state = table_ready.borrow().clone();
+
+            let message = match state {
+                TableReadyState::Ready => return Ok(()),
+                TableReadyState::Failed(message) => message,
+                TableReadyState::Pending => match table_ready.changed().await {
+                    Ok(()) => continue,
+                    Err(_) => "Table creation task is stopped".to_string(),
+                },
+            };
+
+            return Err(DataWriterError::Error {
+                ctx: ErrorContext::new(
+                    "wait_until_ready",
+                    TEntity::TABLE_NAME,
+                    self.executor.settings.get_url().await,
+                ),
+                message,
+            });
+        }
+    }
+
+    /// Returns a copy of the writer with the time limit overridden. Meant to be used for a single call:
+    /// `writer.with_deadline(Duration::from_secs(1)).get_entity(..)`
+    pub fn with_deadline(&self, timeout: Duration) -> Self {
+        let mut executor = self.executor.clone();
+        executor.timeout = Some(timeout);
+
+        Self {
+            executor,
+            sync_period: self.sync_period,
+            default_update_read_statistics: self.default_update_read_statistics.clone(),
+            batch_read_concurrency: self.batch_read_concurrency,
+            bulk_chunk_params: self.bulk_chunk_params.clone(),
+            table_ready: self.table_ready.clone(),
+            itm: None,
+        }
+    }
+
+    /// Transaction against the same server with the same headers, time limit, retry policy,
+    /// request limiter and sync period. It is not bound to the table of the writer - steps for
+    /// any tables can be added.
+    pub fn create_transaction(&self) -> MyNoSqlTransaction {
+        MyNoSqlTransaction::from_executor(self.executor.clone()).with_sync_period(self.sync_period)
+    }
+
+    async fn get_fl_url(&self) -> FlUrl {
+        self.executor.get_fl_url().await
+    }
+
+    async fn execute<TFuture: Future<Output = Result<FlUrlResponse, FlUrlError>>>(
+        &self,
+        process_name: &'static str,
+        idempotent: bool,
+        request: impl Fn() -> TFuture,
+    ) -> Result<WriterResponse, DataWriterError> {
+        self.executor
+            .execute(process_name, TEntity::TABLE_NAME, idempotent, request)
+            .await
+    }
 
-        let fl_url = params.populate_params(fl_url);
+    pub async fn create_table(&self, params: CreateTableParams) -> Result<(), DataWriterError> {
+        let params = &params;
 
-        let mut response = fl_url.post(None).await?;
+        let mut response = self
+            .execute("create_table", false, || async move {
+                let fl_url = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment("Tables")
+                    .append_path_segment("Create")
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .append_data_sync_period(&self.sync_period);
+
+                params.populate_params(fl_url).post(None).await
+            })
+            .await?;
 
-        create_table_errors_handler(&mut response, "create_table", url.as_str()).await
+        response.check_error().await
     }
 
     pub async fn create_table_if_not_exists(
         &self,
         params: CreateTableParams,
     ) -> Result<(), DataWriterError> {
-        create_table_if_not_exists(
-            self.settings.clone(),
-            TEntity::TABLE_NAME,
-            params,
-            self.sync_period,
-        )
-        .await
+        let params = &params;
+
+        let mut response = self
+            .execute("create_table_if_not_exists", true, || async move {
+                let fl_url = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment("Tables")
+                    .append_path_segment("CreateIfNotExists")
+                    .append_data_sync_period(&self.sync_period)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME);
+
+                params.populate_params(fl_url).post(None).await
+            })
+            .await?;
+
+        response.check_error().await
     }
 
     pub async fn insert_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
-        let response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .append_path_segment("Insert")
-            .append_data_sync_period(&self.sync_period)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .post(serialize_entity_to_body(entity))
+        let body = &serialize_entity_to_body(entity, "insert_entity")?;
+
+        let mut response = self
+            .execute(
+                "insert_entity",
+                self.executor.retry_policy.retry_insert_entity,
+                || async move {
+                    self.get_fl_url()
+                        .await
+                        .append_path_segment(ROW_CONTROLLER)
+                        .append_path_segment("Insert")
+                        .append_data_sync_period(&self.sync_period)
+                        .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                        .post(body.clone())
+                        .await
+                },
+            )
             .await?;
 
-        if is_ok_result(&response) {
-            return Ok(());
-        }
+        response.check_error().await?;
 
-        let reason = response.receive_body().await?;
-        let reason = String::from_utf8(reason)?;
-        return Err(DataWriterError::Error(reason));
+        return Ok(());
     }
 
     pub async fn insert_or_replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
-        let response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .append_path_segment("InsertOrReplace")
-            .append_data_sync_period(&self.sync_period)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .post(serialize_entity_to_body(entity))
+        let body = &serialize_entity_to_body(entity, "insert_or_replace_entity")?;
+
+        let mut response = self
+            .execute("insert_or_replace_entity", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .append_path_segment("InsertOrReplace")
+                    .append_data_sync_period(&self.sync_period)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .post(body.clone())
+                    .await
+            })
             .await?;
 
-        if is_ok_result(&response) {
-            return Ok(());
+        response.check_error().await?;
+
+        return Ok(());
+    }
+
+    /// Replaces the row only if it exists and was not changed since the entity was read.
+    /// Unlike `insert_or_replace_entity` it never creates the row: `DataWriterError::RecordNotFound`
+    /// is returned if the row is absent (e.g. deleted by another process).
+    /// The entity's `get_time_stamp()` is sent as the expected version, and
+    /// `DataWriterError::RecordIsChanged` is returned if the server holds a newer one.
+    pub async fn replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        let body = &serialize_entity_to_body(entity, "replace_entity")?;
+
+        let mut response = self
+            .execute("replace_entity", false, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .append_path_segment("Replace")
+                    .append_data_sync_period(&self.sync_period)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_time_stamp_as_query_param(entity.get_time_stamp())
+                    .put(body.clone())
+                    .await
+            })
+            .await?;
+
+        // Replace endpoint may answer 404 without a contract when the row is absent
+        match response.check_error().await {
+            Err(DataWriterError::Error { ctx, message }) if ctx.status_code == Some(404) => {
+                Err(DataWriterError::RecordNotFound { ctx, message })
+            }
+            result => result,
         }
+    }
+
+    /// Reads the row, applies `update` and writes it back with `replace_entity`.
+    /// If someone changed the row in between, the row is re-read and `update` is
+    /// applied again until `max_attempts` is exhausted.
+    /// Returns `None` if the row does not exist or is deleted before the update is written.
+    pub async fn update_entity(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        max_attempts: usize,
+        update: impl Fn(&mut TEntity) + Send + Sync,
+    ) -> Result<Option<TEntity>, DataWriterError> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let mut entity = match self.get_entity(partition_key, row_key, None).await? {
+                Some(entity) => entity,
+                None => return Ok(None),
+            };
 
-        let reason = response.receive_body().await?;
-        let reason = String::from_utf8(reason)?;
-        return Err(DataWriterError::Error(reason));
+            update(&mut entity);
+
+            match self.replace_entity(&entity).await {
+                Ok(()) => return Ok(Some(entity)),
+                Err(DataWriterError::RecordIsChanged { .. }) if attempt < max_attempts => {}
+                Err(DataWriterError::RecordNotFound { .. }) => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
     }
 
     pub async fn bulk_insert_or_replace(
         &self,
         entities: &[TEntity],
     ) -> Result<(), DataWriterError> {
-        let response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(BULK_CONTROLLER)
-            .append_path_segment("InsertOrReplace")
-            .append_data_sync_period(&self.sync_period)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .post(serialize_entities_to_body(entities))
-            .await?;
+        let chunk_params = match &self.bulk_chunk_params {
+            Some(chunk_params) => chunk_params,
+            None => {
+                let body = serialize_entities_to_body(entities, "bulk_insert_or_replace")?;
+                return self.send_bulk_insert_or_replace(&body).await;
+            }
+        };
 
-        if is_ok_result(&response) {
-            return Ok(());
+        // Chunks are separate requests - if one fails, the previous ones stay written.
+        // Repeating the whole call is safe since rows are inserted or replaced.
+        let chunks =
+            serialize_entities_to_chunks(entities, chunk_params, "bulk_insert_or_replace")?;
+
+        for (_, body) in chunks {
+            self.send_bulk_insert_or_replace(&Some(body)).await?;
         }
 
-        let reason = response.receive_body().await?;
-        let reason = String::from_utf8(reason)?;
-        return Err(DataWriterError::Error(reason));
+        Ok(())
+    }
+
+    async fn send_bulk_insert_or_replace(
+        &self,
+        body: &Option<Vec<u8>>,
+    ) -> Result<(), DataWriterError> {
+        let mut response = self
+            .execute("bulk_insert_or_replace", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(BULK_CONTROLLER)
+                    .append_path_segment("InsertOrReplace")
+                    .append_data_sync_period(&self.sync_period)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .post(body.clone())
+                    .await
+            })
+            .await?;
+
+        response.check_error().await?;
+
+        return Ok(());
     }
 
     pub async fn get_entity(
@@ -183,116 +439,417 @@ impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
         row_key: &str,
         update_read_statistics: Option<UpdateReadStatistics>,
     ) -> Result<Option<TEntity>, DataWriterError> {
-        let mut request = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .with_partition_key_as_query_param(partition_key)
-            .with_row_key_as_query_param(row_key)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME);
-
-        if let Some(update_read_statistics) = update_read_statistics {
-            request = update_read_statistics.fill_fields(request);
-        }
+        let update_read_statistics = update_read_statistics
+            .as_ref()
+            .or(self.default_update_read_statistics.as_ref());
 
-        let mut response = request.get().await?;
+        let mut response = self
+            .execute("get_entity", true, || async move {
+                let mut request = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_partition_key_as_query_param(partition_key)
+                    .with_row_key_as_query_param(row_key)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME);
+
+                if let Some(update_read_statistics) = update_read_statistics {
+                    request = update_read_statistics.fill_fields(request);
+                }
+
+                request.get().await
+            })
+            .await?;
 
         if response.get_status_code() == 404 {
             return Ok(None);
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
-        if is_ok_result(&response) {
-            let entity = deserialize_entity(response.get_body().await?)?;
+        if response.is_ok_result() {
+            let entity = response.deserialize_entity().await?;
             return Ok(Some(entity));
         }
 
         return Ok(None);
     }
 
+    /// Reads many rows by their (partition_key, row_key) with one request per partition.
+    /// Partitions are requested in parallel, limited by the builder's `with_batch_read_concurrency`.
+    /// Every requested key is in the result - with `None` if the row does not exist.
+    pub async fn get_entities(
+        &self,
+        keys: &[(&str, &str)],
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<HashMap<(String, String), Option<TEntity>>, DataWriterError> {
+        let update_read_statistics = update_read_statistics
+            .as_ref()
+            .or(self.default_update_read_statistics.as_ref());
+
+        let mut result: HashMap<(String, String), Option<TEntity>> = keys
+            .iter()
+            .map(|(partition_key, row_key)| {
+                ((partition_key.to_string(), row_key.to_string()), None)
+            })
+            .collect();
+
+        let mut responses =
+            futures::stream::iter(group_row_keys_by_partition(keys).into_iter().map(
+                |(partition_key, row_keys)| {
+                    self.get_partition_rows(partition_key, row_keys, update_read_statistics)
+                },
+            ))
+            .buffer_unordered(self.batch_read_concurrency);
+
+        while let Some(entities) = responses.next().await {
+            for entity in entities? {
+                let key = (
+                    entity.get_partition_key().to_string(),
+                    entity.get_row_key().to_string(),
+                );
+
+                if let Some(value) = result.get_mut(&key) {
+                    *value = Some(entity);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn get_partition_rows(
+        &self,
+        partition_key: &str,
+        row_keys: Vec<&str>,
+        update_read_statistics: Option<&UpdateReadStatistics>,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let body = &serialize_to_body::<TEntity, _>(&row_keys, "get_entities")?;
+
+        let mut response = self
+            .execute("get_entities", true, || async move {
+                let mut request = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment(ROWS_CONTROLLER)
+                    .append_path_segment("SinglePartitionMultipleRows")
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_partition_key_as_query_param(partition_key);
+
+                if let Some(update_read_statistics) = update_read_statistics {
+                    request = update_read_statistics.fill_fields(request);
+                }
+
+                request.post(body.clone()).await
+            })
+            .await?;
+
+        if response.get_status_code() == 404 {
+            return Ok(Vec::new());
+        }
+
+        response.check_error().await?;
+
+        response.deserialize_entities().await
+    }
+
     pub async fn get_by_partition_key(
         &self,
         partition_key: &str,
         update_read_statistics: Option<UpdateReadStatistics>,
     ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
-        let mut request = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .with_partition_key_as_query_param(partition_key)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME);
+        let update_read_statistics = update_read_statistics
+            .as_ref()
+            .or(self.default_update_read_statistics.as_ref());
 
-        if let Some(update_read_statistics) = update_read_statistics {
-            request = update_read_statistics.fill_fields(request);
-        }
-
-        let mut response = request.get().await?;
+        let mut response = self
+            .execute("get_by_partition_key", true, || async move {
+                let mut request = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_partition_key_as_query_param(partition_key)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME);
+
+                if let Some(update_read_statistics) = update_read_statistics {
+                    request = update_read_statistics.fill_fields(request);
+                }
+
+                request.get().await
+            })
+            .await?;
 
         if response.get_status_code() == 404 {
             return Ok(None);
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
-        if is_ok_result(&response) {
-            let entities = deserialize_entities(response.get_body().await?)?;
+        if response.is_ok_result() {
+            let entities = response.deserialize_entities().await?;
             return Ok(Some(entities));
         }
 
         return Ok(None);
     }
 
+    /// Same as `get_by_partition_key`, but entities are yielded one by one while the body is
+    /// being received, so memory does not grow with the size of the partition.
+    /// The writer timeout covers receiving the response headers only.
+    /// An absent partition gives an empty stream.
+    pub async fn get_by_partition_key_stream(
+        &self,
+        partition_key: &str,
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<impl Stream<Item = Result<TEntity, DataWriterError>>, DataWriterError> {
+        let update_read_statistics = update_read_statistics
+            .as_ref()
+            .or(self.default_update_read_statistics.as_ref());
+
+        let mut response = self
+            .executor
+            .execute_streaming(
+                "get_by_partition_key_stream",
+                TEntity::TABLE_NAME,
+                true,
+                || async move {
+                    let mut request = self
+                        .get_fl_url()
+                        .await
+                        .append_path_segment(ROW_CONTROLLER)
+                        .with_partition_key_as_query_param(partition_key)
+                        .with_table_name_as_query_param(TEntity::TABLE_NAME);
+
+                    if let Some(update_read_statistics) = update_read_statistics {
+                        request = update_read_statistics.fill_fields(request);
+                    }
+
+                    request.get().await
+                },
+            )
+            .await?;
+
+        if response.get_status_code() == 404 {
+            return Ok(Either::Left(futures::stream::empty()));
+        }
+
+        response.check_error().await?;
+
+        Ok(Either::Right(response.into_entities_stream()))
+    }
+
+    /// Reads `limit` rows of the partition starting from `skip`. Rows are ordered by row key.
+    pub async fn get_by_partition_key_page(
+        &self,
+        partition_key: &str,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let update_read_statistics = self.default_update_read_statistics.as_ref();
+
+        let mut response = self
+            .execute("get_by_partition_key_page", true, || async move {
+                let mut request = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_partition_key_as_query_param(partition_key)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_paging_as_query_params(skip, limit);
+
+                if let Some(update_read_statistics) = update_read_statistics {
+                    request = update_read_statistics.fill_fields(request);
+                }
+
+                request.get().await
+            })
+            .await?;
+
+        if response.get_status_code() == 404 {
+            return Ok(Vec::new());
+        }
+
+        response.check_error().await?;
+
+        response.deserialize_entities().await
+    }
+
+    /// Walks the partition page by page. The next page is requested only when the previous one
+    /// is consumed, so at most `page_size` entities are kept in memory. Rows written or deleted
+    /// during the walk may shift the pages, so a row can be skipped or returned twice.
+    pub fn get_by_partition_key_paged<'s>(
+        &'s self,
+        partition_key: &'s str,
+        page_size: usize,
+    ) -> impl Stream<Item = Result<TEntity, DataWriterError>> + 's {
+        paged_stream(page_size, move |skip, limit| {
+            self.get_by_partition_key_page(partition_key, skip, limit)
+        })
+    }
+
+    /// Returns up to `max_amount` rows of the partition with row key less or equal to `row_key`,
+    /// starting from the highest one. Handy to read the latest records of a time series.
+    pub async fn get_highest_row_and_below(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        max_amount: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let mut response = self
+            .execute("get_highest_row_and_below", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROWS_CONTROLLER)
+                    .append_path_segment("HighestRowAndBelow")
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_partition_key_as_query_param(partition_key)
+                    .with_row_key_as_query_param(row_key)
+                    .append_query_param("maxAmount", Some(max_amount.to_string()))
+                    .get()
+                    .await
+            })
+            .await?;
+
+        if response.get_status_code() == 404 {
+            return Ok(Vec::new());
+        }
+
+        response.check_error().await?;
+
+        response.deserialize_entities().await
+    }
+
+    /// Returns rows of the partition with `from <= row_key <= to`, ordered by row key.
+    pub async fn get_by_row_key_range(
+        &self,
+        partition_key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        self.get_rows_down_from(partition_key, to, |row_key| row_key >= from)
+            .await
+    }
+
+    /// Returns rows of the partition which row key starts with `prefix`, ordered by row key.
+    pub async fn get_by_row_key_prefix(
+        &self,
+        partition_key: &str,
+        prefix: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        // Row keys are compared as bytes, so every key with the prefix is below prefix + char::MAX
+        // (except the ones which continue with char::MAX itself)
+        let to = format!("{}{}", prefix, char::MAX);
+
+        self.get_rows_down_from(partition_key, &to, |row_key| row_key.starts_with(prefix))
+            .await
+    }
+
+    // Walks the partition down from row_key with HighestRowAndBelow pages until in_range is false
+    async fn get_rows_down_from(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        in_range: impl Fn(&str) -> bool,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let mut result = Vec::new();
+        let mut row_key = row_key.to_string();
+        let mut include_row_key = true;
+
+        loop {
+            let page = self
+                .get_highest_row_and_below(partition_key, &row_key, ROW_KEY_RANGE_PAGE_SIZE)
+                .await?;
+
+            let page_len = page.len();
+            let mut last_row_key = None;
+
+            for entity in page {
+                // Next page starts with the last row of the previous one
+                if !include_row_key && entity.get_row_key() >= row_key.as_str() {
+                    continue;
+                }
+
+                if !in_range(entity.get_row_key()) {
+                    result.reverse();
+                    return Ok(result);
+                }
+
+                last_row_key = Some(entity.get_row_key().to_string());
+                result.push(entity);
+            }
+
+            match last_row_key {
+                Some(last_row_key) if page_len >= ROW_KEY_RANGE_PAGE_SIZE => {
+                    row_key = last_row_key;
+                    include_row_key = false;
+                }
+                _ => break,
+            }
+        }
+
+        result.reverse();
+        Ok(result)
+    }
+
     pub async fn get_by_row_key(
         &self,
         row_key: &str,
     ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .with_row_key_as_query_param(row_key)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .get()
+            .execute("get_by_row_key", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_row_key_as_query_param(row_key)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .get()
+                    .await
+            })
             .await?;
 
         if response.get_status_code() == 404 {
             return Ok(None);
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
-        if is_ok_result(&response) {
-            let entities = deserialize_entities(response.get_body().await?)?;
+        if response.is_ok_result() {
+            let entities = response.deserialize_entities().await?;
             return Ok(Some(entities));
         }
 
         return Ok(None);
     }
 
+    /// Returns the deleted row or `None` if there was no such row.
+    /// The request is retried as any idempotent one: if the response of an attempt which deleted
+    /// the row is lost, the repeated attempt does not find the row and `None` is returned.
     pub async fn delete_row(
         &self,
         partition_key: &str,
         row_key: &str,
     ) -> Result<Option<TEntity>, DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .with_partition_key_as_query_param(partition_key)
-            .with_row_key_as_query_param(row_key)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .delete()
+            .execute("delete_row", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_partition_key_as_query_param(partition_key)
+                    .with_row_key_as_query_param(row_key)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .delete()
+                    .await
+            })
             .await?;
 
         if response.get_status_code() == 404 {
             return Ok(None);
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
         if response.get_status_code() == 200 {
-            let entity = deserialize_entity(response.get_body().await?)?;
+            let entity = response.deserialize_entity().await?;
             return Ok(Some(entity));
         }
 
@@ -301,204 +858,372 @@ impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
 
     pub async fn delete_partitions(&self, partition_keys: &[&str]) -> Result<(), DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROWS_CONTROLLER)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .with_partition_keys_as_query_param(partition_keys)
-            .delete()
+            .execute("delete_partitions", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROWS_CONTROLLER)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_partition_keys_as_query_param(partition_keys)
+                    .delete()
+                    .await
+            })
             .await?;
 
         if response.get_status_code() == 404 {
             return Ok(());
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
         return Ok(());
     }
 
+    /// Deletes the given (partition_key, row_key) rows with a single request.
+    /// Returns the rows which were deleted if the server answers with a json array of them -
+    /// keys of absent rows are not in the result. `None` means the delete is confirmed, but the
+    /// response does not tell which rows were deleted (e.g. an empty body).
+    /// The request is retried the same way as `delete_row`, so rows deleted by an attempt whose
+    /// response was lost are not reported by the next one.
+    pub async fn bulk_delete_rows(
+        &self,
+        keys: &[(&str, &str)],
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        if keys.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let body = &serialize_row_keys_to_body::<TEntity>(keys, "bulk_delete_rows")?;
+
+        let mut response = self
+            .execute("bulk_delete_rows", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(BULK_CONTROLLER)
+                    .append_path_segment("Delete")
+                    .append_data_sync_period(&self.sync_period)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .post(body.clone())
+                    .await
+            })
+            .await?;
+
+        response.check_error().await?;
+
+        // Rows are deleted already, so a body which is not an array of entities is not an error
+        let body = response.get_body().await?;
+        Ok(serde_json::from_slice(body).ok())
+    }
+
     pub async fn get_all(&self) -> Result<Option<Vec<TEntity>>, DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(ROW_CONTROLLER)
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .get()
+            .execute("get_all", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .get()
+                    .await
+            })
             .await?;
 
         if response.get_status_code() == 404 {
             return Ok(None);
         }
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
-        if is_ok_result(&response) {
-            let entities = deserialize_entities(response.get_body().await?)?;
+        if response.is_ok_result() {
+            let entities = response.deserialize_entities().await?;
             return Ok(Some(entities));
         }
 
         return Ok(None);
     }
 
-    pub async fn clean_table_and_bulk_insert(
+    /// Same as `get_all`, but entities are yielded while the body is being received.
+    /// See `get_by_partition_key_stream`.
+    pub async fn get_all_stream(
         &self,
-        entities: &[TEntity],
-    ) -> Result<(), DataWriterError> {
+    ) -> Result<impl Stream<Item = Result<TEntity, DataWriterError>>, DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(BULK_CONTROLLER)
-            .append_path_segment("CleanAndBulkInsert")
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .append_data_sync_period(&self.sync_period)
-            .post(serialize_entities_to_body(entities))
+            .executor
+            .execute_streaming("get_all_stream", TEntity::TABLE_NAME, true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .get()
+                    .await
+            })
+            .await?;
+
+        if response.get_status_code() == 404 {
+            return Ok(Either::Left(futures::stream::empty()));
+        }
+
+        response.check_error().await?;
+
+        Ok(Either::Right(response.into_entities_stream()))
+    }
+
+    /// Reads `limit` rows of the table starting from `skip`.
+    /// Rows are ordered by partition key and row key.
+    pub async fn get_all_page(
+        &self,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        let mut response = self
+            .execute("get_all_page", true, || async move {
+                self.get_fl_url()
+                    .await
+                    .append_path_segment(ROW_CONTROLLER)
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .with_paging_as_query_params(skip, limit)
+                    .get()
+                    .await
+            })
             .await?;
 
-        check_error(&mut response).await?;
+        if response.get_status_code() == 404 {
+            return Ok(Vec::new());
+        }
 
-        return Ok(());
+        response.check_error().await?;
+
+        response.deserialize_entities().await
+    }
+
+    /// Walks the whole table page by page. Same as `get_by_partition_key_paged` but for the table.
+    pub fn get_all_paged(
+        &self,
+        page_size: usize,
+    ) -> impl Stream<Item = Result<TEntity, DataWriterError>> + '_ {
+        paged_stream(page_size, move |skip, limit| self.get_all_page(skip, limit))
+    }
+
+    pub async fn clean_table_and_bulk_insert(
+        &self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        self.clean_and_bulk_insert(None, entities, "clean_table_and_bulk_insert")
+            .await
     }
 
     pub async fn clean_partition_and_bulk_insert(
         &self,
         partition_key: &str,
         entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        self.clean_and_bulk_insert(
+            Some(partition_key),
+            entities,
+            "clean_partition_and_bulk_insert",
+        )
+        .await
+    }
+
+    // Upload which does not fit into one request is sent as a transaction, so the server cleans
+    // the data only together with inserting all the chunks and never leaves it half-cleaned
+    async fn clean_and_bulk_insert(
+        &self,
+        partition_key: Option<&str>,
+        entities: &[TEntity],
+        process_name: &'static str,
+    ) -> Result<(), DataWriterError> {
+        let chunk_params = match &self.bulk_chunk_params {
+            Some(chunk_params) => chunk_params,
+            None => {
+                let body = serialize_entities_to_body(entities, process_name)?;
+                return self
+                    .send_clean_and_bulk_insert(partition_key, &body, process_name)
+                    .await;
+            }
+        };
+
+        let step_chunk_params = BulkChunkParams {
+            max_body_size: chunk_params
+                .max_body_size
+                .saturating_sub(TRANSACTION_STEP_OVERHEAD),
+            ..chunk_params.clone()
+        };
+
+        let mut chunks = serialize_entities_to_chunks(entities, &step_chunk_params, process_name)?;
+
+        if chunks.len() <= 1 {
+            let body = match chunks.pop() {
+                Some((_, body)) => Some(body),
+                None => serialize_entities_to_body(entities, process_name)?,
+            };
+
+            return self
+                .send_clean_and_bulk_insert(partition_key, &body, process_name)
+                .await;
+        }
+
+        let mut transaction = self
+            .create_transaction()
+            .with_max_append_body_size(chunk_params.max_body_size);
+
+        match partition_key {
+            Some(partition_key) => transaction.delete_partitions::<TEntity>(&[partition_key]),
+            None => transaction.clean_table::<TEntity>(),
+        }
+
+        // Chunks go into the transaction as they are, the entities are not serialized again
+        for (_, body) in chunks {
+            transaction.insert_or_replace_serialized_entities::<TEntity>(&body)?;
+        }
+
+        transaction.commit().await
+    }
+
+    async fn send_clean_and_bulk_insert(
+        &self,
+        partition_key: Option<&str>,
+        body: &Option<Vec<u8>>,
+        process_name: &'static str,
     ) -> Result<(), DataWriterError> {
         let mut response = self
-            .get_fl_url()
-            .await
-            .append_path_segment(BULK_CONTROLLER)
-            .append_path_segment("CleanAndBulkInsert")
-            .with_table_name_as_query_param(TEntity::TABLE_NAME)
-            .append_data_sync_period(&self.sync_period)
-            .with_partition_key_as_query_param(partition_key)
-            .post(serialize_entities_to_body(entities))
+            .execute(process_name, true, || async move {
+                let mut fl_url = self
+                    .get_fl_url()
+                    .await
+                    .append_path_segment(BULK_CONTROLLER)
+                    .append_path_segment("CleanAndBulkInsert")
+                    .with_table_name_as_query_param(TEntity::TABLE_NAME)
+                    .append_data_sync_period(&self.sync_period);
+
+                if let Some(partition_key) = partition_key {
+                    fl_url = fl_url.with_partition_key_as_query_param(partition_key);
+                }
+
+                fl_url.post(body.clone()).await
+            })
             .await?;
 
-        check_error(&mut response).await?;
+        response.check_error().await?;
 
         return Ok(());
     }
 }
 
-fn is_ok_result(response: &FlUrlResponse) -> bool {
-    response.get_status_code() >= 200 && response.get_status_code() < 300
-}
+// Splits entities into json array bodies limited by amount and size.
+// Entity which alone is bigger than max_body_size goes as a separate chunk
+fn serialize_entities_to_chunks<TEntity: MyNoSqlEntity + Serialize>(
+    entities: &[TEntity],
+    params: &BulkChunkParams,
+    process_name: &'static str,
+) -> Result<Vec<(Range<usize>, Vec<u8>)>, DataWriterError> {
+    let mut chunker = JsonArrayChunker::new(params.max_entities_amount, params.max_body_size);
 
-fn deserialize_entity<TEntity: DeserializeOwned>(src: &[u8]) -> Result<TEntity, DataWriterError> {
-    let src = std::str::from_utf8(src)?;
-    match serde_json::from_str(src) {
-        Ok(result) => Ok(result),
-        Err(err) => {
-            return Err(DataWriterError::Error(format!(
-                "Failed to deserialize entity: {:?}",
-                err
-            )))
-        }
+    for entity in entities {
+        let entity_json =
+            serde_json::to_vec(entity).map_err(|err| DataWriterError::SerializationFailed {
+                ctx: ErrorContext::new(process_name, TEntity::TABLE_NAME, String::new()),
+                err,
+            })?;
+
+        chunker.push(&entity_json);
     }
+
+    Ok(chunker.finish())
 }
 
-fn deserialize_entities<TEntity: DeserializeOwned>(
-    src: &[u8],
-) -> Result<Vec<TEntity>, DataWriterError> {
-    let src = std::str::from_utf8(src)?;
-    match serde_json::from_str(src) {
-        Ok(result) => Ok(result),
-        Err(err) => {
-            return Err(DataWriterError::Error(format!(
-                "Failed to deserialize entity: {:?}",
-                err
-            )))
-        }
+fn serialize_entity_to_body<TEntity: MyNoSqlEntity + Serialize>(
+    entity: &TEntity,
+    process_name: &'static str,
+) -> Result<Option<Vec<u8>>, DataWriterError> {
+    match serde_json::to_vec(entity) {
+        Ok(result) => Ok(Some(result)),
+        Err(err) => Err(DataWriterError::SerializationFailed {
+            ctx: ErrorContext::new(process_name, TEntity::TABLE_NAME, String::new()),
+            err,
+        }),
     }
 }
 
-fn serialize_entity_to_body<TEntity: Serialize>(entity: &TEntity) -> Option<Vec<u8>> {
-    serde_json::to_string(&entity).unwrap().into_bytes().into()
+fn serialize_entities_to_body<TEntity: MyNoSqlEntity + Serialize>(
+    entities: &[TEntity],
+    process_name: &'static str,
+) -> Result<Option<Vec<u8>>, DataWriterError> {
+    match serde_json::to_vec(entities) {
+        Ok(result) => Ok(Some(result)),
+        Err(err) => Err(DataWriterError::SerializationFailed {
+            ctx: ErrorContext::new(process_name, TEntity::TABLE_NAME, String::new()),
+            err,
+        }),
+    }
 }
 
-fn serialize_entities_to_body<TEntity: Serialize>(entities: &[TEntity]) -> Option<Vec<u8>> {
-    serde_json::to_string(&entities)
-        .unwrap()
-        .into_bytes()
-        .into()
+// Page which is shorter than page_size is the last one. Error ends the stream
+fn paged_stream<
+    's,
+    TEntity: 's,
+    TFuture: Future<Output = Result<Vec<TEntity>, DataWriterError>> + 's,
+>(
+    page_size: usize,
+    get_page: impl Fn(usize, usize) -> TFuture + 's,
+) -> impl Stream<Item = Result<TEntity, DataWriterError>> + 's {
+    let page_size = page_size.max(1);
+
+    futures::stream::unfold(Some(0), move |skip: Option<usize>| {
+        let page = skip.map(|skip| (skip, get_page(skip, page_size)));
+
+        async move {
+            let (skip, page) = page?;
+
+            let entities = match page.await {
+                Ok(entities) => entities,
+                Err(err) => return Some((futures::stream::iter(vec![Err(err)]), None)),
+            };
+
+            let next_skip = if entities.len() < page_size {
+                None
+            } else {
+                Some(skip + entities.len())
+            };
+
+            let entities: Vec<_> = entities.into_iter().map(Ok).collect();
+
+            Some((futures::stream::iter(entities), next_skip))
+        }
+    })
+    .flatten()
 }
 
-async fn check_error(response: &mut FlUrlResponse) -> Result<(), DataWriterError> {
-    let result = match response.get_status_code() {
-        400 => Err(deserialize_error(response).await?),
-
-        409 => Err(DataWriterError::TableNotFound("".to_string())),
-        _ => Ok(()),
-    };
+// Bulk endpoints address rows as partition_key -> [row_key]
+fn group_row_keys_by_partition<'s>(keys: &[(&'s str, &'s str)]) -> BTreeMap<&'s str, Vec<&'s str>> {
+    let mut result: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
 
-    if let Err(err) = &result {
-        my_logger::LOGGER.write_error(
-            format!("FlUrlRequest to {}", response.url.to_string()),
-            format!("{:?}", err),
-            None.into(),
-        );
+    for (partition_key, row_key) in keys {
+        result.entry(*partition_key).or_default().push(*row_key);
     }
 
     result
 }
 
-async fn create_table_errors_handler(
-    response: &mut FlUrlResponse,
+fn serialize_row_keys_to_body<TEntity: MyNoSqlEntity>(
+    keys: &[(&str, &str)],
     process_name: &'static str,
-    url: &str,
-) -> Result<(), DataWriterError> {
-    if is_ok_result(response) {
-        return Ok(());
-    }
-
-    let result = deserialize_error(response).await?;
-
-    my_logger::LOGGER.write_error(
-        process_name,
-        format!("{:?}", result),
-        LogEventCtx::new().add("URL", url),
-    );
-
-    Err(result)
+) -> Result<Option<Vec<u8>>, DataWriterError> {
+    serialize_to_body::<TEntity, _>(&group_row_keys_by_partition(keys), process_name)
 }
-#[derive(Serialize, Deserialize, Debug)]
-pub struct OperationFailHttpContract {
-    pub reason: String,
-    pub message: String,
-}
-
-async fn deserialize_error(
-    response: &mut FlUrlResponse,
-) -> Result<DataWriterError, DataWriterError> {
-    let body = response.get_body().await?;
-
-    let body_as_str = std::str::from_utf8(body)?;
 
-    let result = match serde_json::from_str::<OperationFailHttpContract>(body_as_str) {
-        Ok(fail_contract) => match fail_contract.reason.as_str() {
-            "TableAlreadyExists" => DataWriterError::TableAlreadyExists(fail_contract.message),
-            "TableNotFound" => DataWriterError::TableNotFound(fail_contract.message),
-            "RecordAlreadyExists" => DataWriterError::RecordAlreadyExists(fail_contract.message),
-            "RequiredEntityFieldIsMissing" => {
-                DataWriterError::RequiredEntityFieldIsMissing(fail_contract.message)
-            }
-            "JsonParseFail" => DataWriterError::ServerCouldNotParseJson(fail_contract.message),
-            _ => DataWriterError::Error(format!("Not supported error. {:?}", fail_contract)),
-        },
-        Err(err) => {
-            return Err(DataWriterError::Error(format!(
-                "Failed to deserialize error: {:?}",
-                err
-            )))
-        }
-    };
-
-    Ok(result)
+fn serialize_to_body<TEntity: MyNoSqlEntity, TBody: Serialize>(
+    body: &TBody,
+    process_name: &'static str,
+) -> Result<Option<Vec<u8>>, DataWriterError> {
+    match serde_json::to_vec(body) {
+        Ok(result) => Ok(Some(result)),
+        Err(err) => Err(DataWriterError::SerializationFailed {
+            ctx: ErrorContext::new(process_name, TEntity::TABLE_NAME, String::new()),
+            err,
+        }),
+    }
 }
 
-trait FlUrlExt {
+pub(crate) trait FlUrlExt {
     fn with_table_name_as_query_param(self, table_name: &str) -> FlUrl;
 
     fn append_data_sync_period(self, sync_period: &DataSynchronizationPeriod) -> FlUrl;
@@ -508,6 +1233,12 @@ trait FlUrlExt {
     fn with_row_key_as_query_param(self, partition_key: &str) -> FlUrl;
 
     fn with_persist_as_query_param(self, persist: bool) -> FlUrl;
+
+    fn with_time_stamp_as_query_param(self, time_stamp: i64) -> FlUrl;
+
+    fn with_paging_as_query_params(self, skip: usize, limit: usize) -> FlUrl;
+
+    fn with_headers(self, headers: &[(String, String)]) -> FlUrl;
 }
 
 impl FlUrlExt for FlUrl {
@@ -548,49 +1279,196 @@ impl FlUrlExt for FlUrl {
         let value = if persist { "1" } else { "0" };
         self.append_query_param("persist", Some(value))
     }
+
+    fn with_time_stamp_as_query_param(self, time_stamp: i64) -> FlUrl {
+        self.append_query_param("timeStamp", Some(time_stamp.to_string()))
+    }
+
+    fn with_paging_as_query_params(self, skip: usize, limit: usize) -> FlUrl {
+        self.append_query_param("skip", Some(skip.to_string()))
+            .append_query_param("limit", Some(limit.to_string()))
+    }
+
+    fn with_headers(self, headers: &[(String, String)]) -> FlUrl {
+        let mut s = self;
+        for (name, value) in headers {
+            s = s.with_header(name.as_str(), value.as_str());
+        }
+        s
+    }
 }
 
-async fn create_table_if_not_exists(
-    settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>,
+// Goes through the same executor as the writer, so every attempt is limited by its timeout,
+// retry policy and request limiter. Retryable failures are retried until the table is created
+async fn create_table_in_background(
+    executor: RequestExecutor,
     table_name: &'static str,
     params: CreateTableParams,
     sync_period: DataSynchronizationPeriod,
-) -> Result<(), DataWriterError> {
-    let url = settings.get_url().await;
-    let fl_url = FlUrl::new(url.as_str())
-        .append_path_segment("Tables")
-        .append_path_segment("CreateIfNotExists")
-        .append_data_sync_period(&sync_period)
-        .with_table_name_as_query_param(table_name);
+    sender: watch::Sender<TableReadyState>,
+) {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let err =
+            match create_table_if_not_exists(&executor, table_name, &params, &sync_period).await {
+                Ok(()) => {
+                    let _ = sender.send(TableReadyState::Ready);
+                    return;
+                }
+                Err(err) => err,
+            };
+
+        if !executor.retry_policy.is_retryable_error(&err) {
+            my_logger::LOGGER.write_error(
+                "create_table_in_background",
+                format!("Table {} can not be created: {}", table_name, err),
+                LogEventCtx::new().add("TableName", table_name),
+            );
+
+            let _ = sender.send(TableReadyState::Failed(err.to_string()));
+            return;
+        }
 
-    let fl_url = params.populate_params(fl_url);
+        let delay = executor.retry_policy.get_delay(attempt);
 
-    let mut response = fl_url.post(None).await?;
+        my_logger::LOGGER.write_warning(
+            "create_table_in_background",
+            format!(
+                "Attempt {} to create table {} failed: {}. Retrying in {:?}",
+                attempt, table_name, err, delay
+            ),
+            LogEventCtx::new().add("TableName", table_name),
+        );
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+async fn create_table_if_not_exists(
+    executor: &RequestExecutor,
+    table_name: &'static str,
+    params: &CreateTableParams,
+    sync_period: &DataSynchronizationPeriod,
+) -> Result<(), DataWriterError> {
+    let mut response = executor
+        .execute(
+            "create_table_if_not_exists",
+            table_name,
+            true,
+            || async move {
+                let fl_url = executor
+                    .get_fl_url()
+                    .await
+                    .append_path_segment("Tables")
+                    .append_path_segment("CreateIfNotExists")
+                    .append_data_sync_period(sync_period)
+                    .with_table_name_as_query_param(table_name);
+
+                params.populate_params(fl_url).post(None).await
+            },
+        )
+        .await?;
 
-    create_table_errors_handler(&mut response, "create_table_if_not_exists", url.as_str()).await
+    response.check_error().await
 }
 
 #[cfg(test)]
 mod tests {
-    use my_no_sql_server_abstractions::MyNoSqlEntity;
+    use std::{collections::HashMap, time::Duration};
+
+    use futures::StreamExt;
+    use my_no_sql_server_abstractions::{DataSynchronizationPeriod, MyNoSqlEntity};
     use serde::Serialize;
 
+    use crate::{
+        test_fixtures::{create_writer, TestEntity},
+        BulkChunkParams, CreateTableParams, DataWriterError, MockFault, MockMyNoSqlServer,
+        MyNoSqlDataWriter, RetryPolicy, TableReadyState,
+    };
+
+    #[test]
+    fn test() {
+        let entities = vec![
+            TestEntity::new("1", "1", 0),
+            TestEntity::new("1", "2", 0),
+            TestEntity::new("2", "1", 0),
+            TestEntity::new("2", "2", 0),
+        ];
+
+        let as_json = super::serialize_entities_to_body(&entities, "test")
+            .unwrap()
+            .unwrap();
+
+        println!("{}", std::str::from_utf8(&as_json).unwrap());
+    }
+
+    #[test]
+    fn test_row_keys_are_grouped_by_partition() {
+        let body = super::serialize_row_keys_to_body::<TestEntity>(
+            &[("1", "a"), ("2", "b"), ("1", "c")],
+            "test",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            r#"{"1":["a","c"],"2":["b"]}"#
+        );
+    }
+
+    #[test]
+    fn test_entities_are_split_into_chunks() {
+        let entities: Vec<TestEntity> = (0..5)
+            .map(|i| TestEntity::new("1", &i.to_string(), 0))
+            .collect();
+
+        // Every entity is 57 bytes: {"PartitionKey":"1","RowKey":"0","TimeStamp":0,"Value":0}
+        let params = super::BulkChunkParams {
+            max_entities_amount: 2,
+            max_body_size: 1024,
+        };
+
+        let chunks = super::serialize_entities_to_chunks(&entities, &params, "test").unwrap();
+        let ranges: Vec<_> = chunks.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, vec![0..2, 2..4, 4..5]);
+
+        let params = super::BulkChunkParams {
+            max_entities_amount: 100,
+            max_body_size: 120,
+        };
+
+        let chunks = super::serialize_entities_to_chunks(&entities, &params, "test").unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        for (range, body) in chunks {
+            assert!(body.len() <= 120);
+
+            let parsed: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+            assert_eq!(parsed.len(), range.len());
+        }
+
+        let chunks = super::serialize_entities_to_chunks(&entities[..0], &params, "test").unwrap();
+        assert!(chunks.is_empty());
+    }
+
     #[derive(Debug, Serialize)]
-    #[serde(rename_all = "PascalCase")]
-    struct TestEntity {
-        partition_key: String,
-        row_key: String,
+    struct EntityWithNonStringKeys {
+        map: HashMap<(u8, u8), String>,
     }
 
-    impl MyNoSqlEntity for TestEntity {
+    impl MyNoSqlEntity for EntityWithNonStringKeys {
         const TABLE_NAME: &'static str = "test";
 
         fn get_partition_key(&self) -> &str {
-            &self.partition_key
+            "pk"
         }
 
         fn get_row_key(&self) -> &str {
-            &self.row_key
+            "rk"
         }
 
         fn get_time_stamp(&self) -> i64 {
@@ -599,28 +1477,562 @@ mod tests {
     }
 
     #[test]
-    fn test() {
-        let entities = vec![
-            TestEntity {
-                partition_key: "1".to_string(),
-                row_key: "1".to_string(),
+    fn test_serialization_error_does_not_panic() {
+        let mut map = HashMap::new();
+        map.insert((1, 1), "value".to_string());
+
+        let result =
+            super::serialize_entity_to_body(&EntityWithNonStringKeys { map }, "insert_entity");
+
+        assert!(matches!(
+            result,
+            Err(DataWriterError::SerializationFailed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_writer_against_mock_server() {
+        let server = MockMyNoSqlServer::start().await;
+
+        let writer: MyNoSqlDataWriter<TestEntity> = MyNoSqlDataWriter::new(
+            server.get_settings(),
+            Some(CreateTableParams {
+                persist: true,
+                max_partitions_amount: None,
+                max_rows_per_partition_amount: None,
+            }),
+            DataSynchronizationPeriod::Sec1,
+        );
+
+        writer.wait_until_ready().await.unwrap();
+
+        let entity = TestEntity {
+            partition_key: "pk".to_string(),
+            row_key: "rk".to_string(),
+            time_stamp: 0,
+            value: 1,
+        };
+
+        writer.insert_entity(&entity).await.unwrap();
+
+        let result = writer.insert_entity(&entity).await;
+        assert!(matches!(
+            result,
+            Err(DataWriterError::RecordAlreadyExists { .. })
+        ));
+
+        let mut stale_entity = writer.get_entity("pk", "rk", None).await.unwrap().unwrap();
+
+        writer
+            .update_entity("pk", "rk", 3, |entity| entity.value += 1)
+            .await
+            .unwrap();
+
+        stale_entity.value = 100;
+        let result = writer.replace_entity(&stale_entity).await;
+        assert!(matches!(
+            result,
+            Err(DataWriterError::RecordIsChanged { .. })
+        ));
+
+        let requests = server.get_requests();
+        let insert_request = requests
+            .iter()
+            .find(|request| request.path == "/Row/Insert")
+            .unwrap();
+        assert_eq!(insert_request.get_query_param("tableName"), Some("test"));
+        assert_eq!(insert_request.get_query_param("syncPeriod"), Some("1"));
+
+        let deleted = writer.delete_row("pk", "rk").await.unwrap().unwrap();
+        assert_eq!(deleted.value, 2);
+
+        assert!(writer.get_entity("pk", "rk", None).await.unwrap().is_none());
+        assert_eq!(server.get_rows_amount("test"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready_reports_failed_table_creation() {
+        let server = MockMyNoSqlServer::start().await;
+
+        // Transient failure is retried in the background, the next one is definitive
+        server.inject_fault_for_path(
+            "/Tables/CreateIfNotExists",
+            MockFault::ServerError {
+                status: 503,
+                body: "Restarting".to_string(),
             },
-            TestEntity {
-                partition_key: "1".to_string(),
-                row_key: "2".to_string(),
+        );
+        server.inject_fault_for_path(
+            "/Tables/CreateIfNotExists",
+            MockFault::ServerError {
+                status: 500,
+                body: "Persistence is not available".to_string(),
             },
-            TestEntity {
-                partition_key: "2".to_string(),
-                row_key: "1".to_string(),
+        );
+
+        let writer: MyNoSqlDataWriter<TestEntity> =
+            MyNoSqlDataWriter::builder(server.get_settings())
+                .with_retry_policy(RetryPolicy {
+                    initial_delay: Duration::from_millis(10),
+                    ..RetryPolicy::no_retries()
+                })
+                .with_auto_create_table(CreateTableParams {
+                    persist: true,
+                    max_partitions_amount: None,
+                    max_rows_per_partition_amount: None,
+                })
+                .build();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), writer.wait_until_ready())
+            .await
+            .unwrap();
+
+        match result {
+            Err(DataWriterError::Error { message, .. }) => {
+                assert!(message.contains("Persistence is not available"))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        assert!(matches!(
+            writer.get_table_ready_state(),
+            TableReadyState::Failed(_)
+        ));
+
+        let create_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Tables/CreateIfNotExists")
+            .count();
+        assert_eq!(create_requests, 2);
+    }
+
+    #[tokio::test]
+    async fn test_replace_does_not_resurrect_deleted_row() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        writer
+            .insert_entity(&TestEntity::new("pk", "rk", 1))
+            .await
+            .unwrap();
+
+        let mut entity = writer.get_entity("pk", "rk", None).await.unwrap().unwrap();
+        writer.delete_row("pk", "rk").await.unwrap();
+
+        entity.value = 2;
+        let result = writer.replace_entity(&entity).await;
+        assert!(matches!(
+            result,
+            Err(DataWriterError::RecordNotFound { .. })
+        ));
+        assert_eq!(server.get_rows_amount("test"), 0);
+
+        let result = writer
+            .update_entity("pk", "rk", 3, |entity| entity.value += 1)
+            .await
+            .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_rows_reports_deleted_rows() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        let entities: Vec<TestEntity> = ["rk1", "rk2", "rk3"]
+            .iter()
+            .map(|row_key| TestEntity {
+                row_key: row_key.to_string(),
+                ..TestEntity::new("pk", "rk", 1)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities).await.unwrap();
+
+        let deleted = writer
+            .bulk_delete_rows(&[
+                ("pk", "rk1"),
+                ("pk", "rk3"),
+                ("pk", "missing"),
+                ("pk2", "rk1"),
+            ])
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut deleted: Vec<String> = deleted.into_iter().map(|entity| entity.row_key).collect();
+        deleted.sort();
+        assert_eq!(deleted, vec!["rk1".to_string(), "rk3".to_string()]);
+        assert_eq!(server.get_rows_amount("test"), 1);
+
+        let bulk_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Bulk/Delete")
+            .count();
+        assert_eq!(bulk_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_rows_accepts_empty_response() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        writer
+            .bulk_insert_or_replace(&[
+                TestEntity::new("pk", "rk1", 1),
+                TestEntity::new("pk", "rk2", 2),
+            ])
+            .await
+            .unwrap();
+
+        server.inject_fault_for_path("/Bulk/Delete", MockFault::EmptyBody);
+
+        let deleted = writer
+            .bulk_delete_rows(&[("pk", "rk1"), ("pk", "rk2")])
+            .await
+            .unwrap();
+
+        // Delete is confirmed, but the deleted rows are unknown
+        assert!(deleted.is_none());
+        assert_eq!(server.get_rows_amount("test"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_entities_requests_each_partition_once() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        let entities: Vec<TestEntity> = [("pk1", "rk1"), ("pk1", "rk2"), ("pk2", "rk1")]
+            .iter()
+            .map(|(partition_key, row_key)| TestEntity {
+                partition_key: partition_key.to_string(),
+                row_key: row_key.to_string(),
+                ..TestEntity::new("pk", "rk", 1)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities).await.unwrap();
+
+        let result = writer
+            .get_entities(
+                &[
+                    ("pk1", "rk1"),
+                    ("pk1", "rk2"),
+                    ("pk1", "missing"),
+                    ("pk2", "rk1"),
+                    ("pk3", "rk1"),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 5);
+        assert!(result[&("pk1".to_string(), "rk2".to_string())].is_some());
+        assert!(result[&("pk2".to_string(), "rk1".to_string())].is_some());
+        assert!(result[&("pk1".to_string(), "missing".to_string())].is_none());
+        assert!(result[&("pk3".to_string(), "rk1".to_string())].is_none());
+
+        let read_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Rows/SinglePartitionMultipleRows")
+            .count();
+        assert_eq!(read_requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_paged_stream_walks_all_pages() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        let entities: Vec<TestEntity> = (0..7)
+            .map(|i| TestEntity {
+                row_key: format!("rk{}", i),
+                ..TestEntity::new("pk", "rk", i)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities).await.unwrap();
+
+        let page = writer.get_by_partition_key_page("pk", 2, 3).await.unwrap();
+        let row_keys: Vec<&str> = page.iter().map(|entity| entity.row_key.as_str()).collect();
+        assert_eq!(row_keys, vec!["rk2", "rk3", "rk4"]);
+
+        let values: Vec<i32> = writer
+            .get_by_partition_key_paged("pk", 3)
+            .map(|entity| entity.unwrap().value)
+            .collect()
+            .await;
+        assert_eq!(values, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let page_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| {
+                request.path == "/Row"
+                    && request.get_query_param("partitionKey") == Some("pk")
+                    && request.get_query_param("skip").is_some()
+                    && request.get_query_param("limit").is_some()
+            })
+            .count();
+        assert_eq!(page_requests, 1 + 3);
+
+        let all: Vec<_> = writer.get_all_paged(100).collect().await;
+        assert_eq!(all.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_entities_are_streamed_while_body_is_received() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        let entities: Vec<TestEntity> = (0..3)
+            .map(|i| TestEntity {
+                row_key: format!("rk{}", i),
+                ..TestEntity::new("pk", "rk", i)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities).await.unwrap();
+
+        // First entity takes about 60 bytes, so the second one is cut in the middle
+        server.inject_fault(MockFault::PauseBody { after: 80 });
+
+        let mut stream = Box::pin(
+            writer
+                .get_by_partition_key_stream("pk", None)
+                .await
+                .unwrap(),
+        );
+
+        let first = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("First entity must arrive while the body is paused");
+        assert_eq!(first.unwrap().unwrap().value, 0);
+
+        let second = tokio::time::timeout(Duration::from_millis(100), stream.next()).await;
+        assert!(second.is_err());
+
+        server.resume_paused_bodies();
+
+        let values: Vec<i32> = stream.map(|entity| entity.unwrap().value).collect().await;
+        assert_eq!(values, vec![1, 2]);
+
+        let missing: Vec<_> = writer
+            .get_by_partition_key_stream("missing", None)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(missing.is_empty());
+
+        let all: Vec<_> = writer.get_all_stream().await.unwrap().collect().await;
+        assert_eq!(all.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_row_key_range_and_prefix() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        let row_keys = [
+            "2023-12-31",
+            "2024-01-01",
+            "2024-01-15",
+            "2024-02-01",
+            "2025-01-01",
+        ];
+
+        let entities: Vec<TestEntity> = row_keys
+            .iter()
+            .map(|row_key| TestEntity {
+                row_key: row_key.to_string(),
+                ..TestEntity::new("pk", "rk", 1)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities).await.unwrap();
+
+        let latest = writer
+            .get_highest_row_and_below("pk", "2024-12-31", 2)
+            .await
+            .unwrap();
+        let latest: Vec<&str> = latest.iter().map(|e| e.row_key.as_str()).collect();
+        assert_eq!(latest, vec!["2024-02-01", "2024-01-15"]);
+
+        let range = writer
+            .get_by_row_key_range("pk", "2024-01-01", "2024-02-01")
+            .await
+            .unwrap();
+        let range: Vec<&str> = range.iter().map(|e| e.row_key.as_str()).collect();
+        assert_eq!(range, vec!["2024-01-01", "2024-01-15", "2024-02-01"]);
+
+        let by_prefix = writer.get_by_row_key_prefix("pk", "2024-01").await.unwrap();
+        let by_prefix: Vec<&str> = by_prefix.iter().map(|e| e.row_key.as_str()).collect();
+        assert_eq!(by_prefix, vec!["2024-01-01", "2024-01-15"]);
+
+        let missing = writer.get_by_row_key_prefix("pk2", "2024").await.unwrap();
+        assert!(missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chunked_clean_and_bulk_insert_is_atomic() {
+        let server = MockMyNoSqlServer::start().await;
+        server.create_table(TestEntity::TABLE_NAME);
+
+        let writer: MyNoSqlDataWriter<TestEntity> =
+            MyNoSqlDataWriter::builder(server.get_settings())
+                .with_retry_policy(RetryPolicy::no_retries())
+                .with_bulk_chunking(BulkChunkParams {
+                    max_entities_amount: 3,
+                    ..Default::default()
+                })
+                .build();
+
+        let entities: Vec<TestEntity> = (0..10)
+            .map(|i| TestEntity {
+                row_key: format!("rk{}", i),
+                ..TestEntity::new("pk", "rk", i)
+            })
+            .collect();
+
+        writer.bulk_insert_or_replace(&entities[..5]).await.unwrap();
+        assert_eq!(server.get_rows_amount("test"), 5);
+
+        let bulk_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Bulk/InsertOrReplace")
+            .count();
+        assert_eq!(bulk_requests, 2);
+
+        server.inject_fault_for_path(
+            "/Transactions/Commit",
+            MockFault::ServerError {
+                status: 500,
+                body: "Commit failed".to_string(),
             },
-            TestEntity {
-                partition_key: "2".to_string(),
-                row_key: "2".to_string(),
+        );
+
+        let result = writer.clean_table_and_bulk_insert(&entities[3..]).await;
+        assert!(matches!(result, Err(DataWriterError::ServerError { .. })));
+        assert_eq!(server.get_rows_amount("test"), 5);
+
+        writer
+            .clean_table_and_bulk_insert(&entities[3..])
+            .await
+            .unwrap();
+        assert_eq!(server.get_rows_amount("test"), 7);
+        assert!(writer
+            .get_entity("pk", "rk0", None)
+            .await
+            .unwrap()
+            .is_none());
+
+        let commit = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Transactions/Commit")
+            .last()
+            .unwrap();
+        assert_eq!(commit.get_query_param("syncPeriod"), Some("5"));
+    }
+
+    #[tokio::test]
+    async fn test_server_error_burst_is_retried() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        server.inject_faults(
+            MockFault::ServerError {
+                status: 503,
+                body: "Restarting".to_string(),
             },
-        ];
+            2,
+        );
 
-        let as_json = super::serialize_entities_to_body(&entities).unwrap();
+        writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk", 1))
+            .await
+            .unwrap();
+
+        let attempts = server
+            .get_requests()
+            .iter()
+            .filter(|request| request.path == "/Row/InsertOrReplace")
+            .count();
+        assert_eq!(attempts, 3);
+
+        server.inject_fault(MockFault::ServerError {
+            status: 500,
+            body: "Failure".to_string(),
+        });
+
+        let result = writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk", 2))
+            .await;
+        assert!(matches!(
+            result,
+            Err(DataWriterError::ServerError { status: 500, .. })
+        ));
+    }
 
-        println!("{}", std::str::from_utf8(&as_json).unwrap());
+    #[tokio::test]
+    async fn test_not_retryable_operation_is_not_repeated() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        server.inject_fault_for_path("/Row/Insert", MockFault::DropConnection);
+
+        let result = writer.insert_entity(&TestEntity::new("pk", "rk", 1)).await;
+        assert!(matches!(result, Err(DataWriterError::FlUrlError { .. })));
+        assert_eq!(server.get_rows_amount(TestEntity::TABLE_NAME), 0);
+
+        let insert_requests = server
+            .get_requests()
+            .into_iter()
+            .filter(|request| request.path == "/Row/Insert")
+            .count();
+        assert_eq!(insert_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_error_bodies() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        server.inject_fault(MockFault::UnknownReason {
+            status: 400,
+            reason: "SomethingNew".to_string(),
+        });
+
+        let result = writer.insert_entity(&TestEntity::new("pk", "rk", 1)).await;
+        assert!(matches!(result, Err(DataWriterError::Error { .. })));
+
+        server.inject_fault(MockFault::MalformedJson { status: 409 });
+
+        let result = writer.insert_entity(&TestEntity::new("pk", "rk", 1)).await;
+        match result {
+            Err(DataWriterError::Error { ctx, .. }) => assert_eq!(ctx.status_code, Some(409)),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delayed_response_hits_timeout() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        server.inject_fault(MockFault::Delay(Duration::from_millis(500)));
+
+        let result = writer
+            .with_deadline(Duration::from_millis(100))
+            .get_entity("pk", "rk", None)
+            .await;
+
+        assert!(matches!(result, Err(DataWriterError::Timeout { .. })));
     }
 }
@@ -0,0 +1,105 @@
+use std::{marker::PhantomData, sync::Arc, time::Duration};
+
+use my_no_sql_server_abstractions::{DataSynchronizationPeriod, MyNoSqlEntity};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    BulkChunkParams, CreateTableParams, MyNoSqlDataWriter, MyNoSqlWriterSettings, RequestLimiter,
+    RetryPolicy, UpdateReadStatistics,
+};
+
+const DEFAULT_BATCH_READ_CONCURRENCY: usize = 8;
+
+pub struct MyNoSqlDataWriterBuilder<
+    TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize,
+> {
+    pub(crate) settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>,
+    pub(crate) sync_period: DataSynchronizationPeriod,
+    pub(crate) auto_create_table_params: Option<CreateTableParams>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) default_update_read_statistics: Option<UpdateReadStatistics>,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) batch_read_concurrency: usize,
+    pub(crate) request_limiter: Option<RequestLimiter>,
+    pub(crate) bulk_chunk_params: Option<BulkChunkParams>,
+    itm: PhantomData<TEntity>,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    MyNoSqlDataWriterBuilder<TEntity>
+{
+    pub fn new(settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>) -> Self {
+        Self {
+            settings,
+            sync_period: DataSynchronizationPeriod::Sec5,
+            auto_create_table_params: None,
+            retry_policy: RetryPolicy::default(),
+            timeout: None,
+            default_update_read_statistics: None,
+            headers: Vec::new(),
+            batch_read_concurrency: DEFAULT_BATCH_READ_CONCURRENCY,
+            request_limiter: None,
+            bulk_chunk_params: None,
+            itm: PhantomData,
+        }
+    }
+
+    pub fn with_sync_period(mut self, sync_period: DataSynchronizationPeriod) -> Self {
+        self.sync_period = sync_period;
+        self
+    }
+
+    pub fn with_auto_create_table(mut self, params: CreateTableParams) -> Self {
+        self.auto_create_table_params = Some(params);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Default time limit of every operation including all the retry attempts.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Used by read operations which are called with update_read_statistics = None
+    pub fn with_default_update_read_statistics(
+        mut self,
+        update_read_statistics: UpdateReadStatistics,
+    ) -> Self {
+        self.default_update_read_statistics = Some(update_read_statistics);
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    // Amount of requests get_entities runs in parallel - one request per partition
+    pub fn with_batch_read_concurrency(mut self, batch_read_concurrency: usize) -> Self {
+        self.batch_read_concurrency = batch_read_concurrency.max(1);
+        self
+    }
+
+    // Applies to the transactions created by the writer as well.
+    // Pass a clone of the same limiter to several builders to share the limits between writers
+    pub fn with_request_limiter(mut self, request_limiter: RequestLimiter) -> Self {
+        self.request_limiter = Some(request_limiter);
+        self
+    }
+
+    // Splits bulk uploads which exceed the limits into several requests
+    pub fn with_bulk_chunking(mut self, bulk_chunk_params: BulkChunkParams) -> Self {
+        self.bulk_chunk_params = Some(bulk_chunk_params);
+        self
+    }
+
+    pub fn build(self) -> MyNoSqlDataWriter<TEntity> {
+        MyNoSqlDataWriter::from_builder(self)
+    }
+}
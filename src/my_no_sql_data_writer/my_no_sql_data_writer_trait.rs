@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use my_no_sql_server_abstractions::MyNoSqlEntity;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{DataWriterError, MyNoSqlDataWriter, UpdateReadStatistics};
+
+// Object safe abstraction of the writer, so services can hold Arc<dyn MyNoSqlDataWriterTrait<TEntity>>
+// and get a fake one injected in tests
+#[async_trait::async_trait]
+pub trait MyNoSqlDataWriterTrait<
+    TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize,
+>
+{
+    async fn insert_entity(&self, entity: &TEntity) -> Result<(), DataWriterError>;
+
+    async fn insert_or_replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError>;
+
+    async fn replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError>;
+
+    async fn bulk_insert_or_replace(&self, entities: &[TEntity]) -> Result<(), DataWriterError>;
+
+    async fn get_entity(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<TEntity>, DataWriterError>;
+
+    async fn get_entities(
+        &self,
+        keys: &[(&str, &str)],
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<HashMap<(String, String), Option<TEntity>>, DataWriterError>;
+
+    async fn get_by_partition_key(
+        &self,
+        partition_key: &str,
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError>;
+
+    async fn get_by_partition_key_page(
+        &self,
+        partition_key: &str,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError>;
+
+    async fn get_highest_row_and_below(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        max_amount: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError>;
+
+    async fn get_by_row_key_range(
+        &self,
+        partition_key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError>;
+
+    async fn get_by_row_key_prefix(
+        &self,
+        partition_key: &str,
+        prefix: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError>;
+
+    async fn get_by_row_key(&self, row_key: &str) -> Result<Option<Vec<TEntity>>, DataWriterError>;
+
+    async fn get_all(&self) -> Result<Option<Vec<TEntity>>, DataWriterError>;
+
+    async fn get_all_page(
+        &self,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError>;
+
+    async fn delete_row(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+    ) -> Result<Option<TEntity>, DataWriterError>;
+
+    async fn delete_partitions(&self, partition_keys: &[&str]) -> Result<(), DataWriterError>;
+
+    async fn bulk_delete_rows(
+        &self,
+        keys: &[(&str, &str)],
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError>;
+
+    async fn clean_table_and_bulk_insert(
+        &self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError>;
+
+    async fn clean_partition_and_bulk_insert(
+        &self,
+        partition_key: &str,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError>;
+}
+
+#[async_trait::async_trait]
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    MyNoSqlDataWriterTrait<TEntity> for MyNoSqlDataWriter<TEntity>
+{
+    async fn insert_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::insert_entity(self, entity).await
+    }
+
+    async fn insert_or_replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::insert_or_replace_entity(self, entity).await
+    }
+
+    async fn replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::replace_entity(self, entity).await
+    }
+
+    async fn bulk_insert_or_replace(&self, entities: &[TEntity]) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::bulk_insert_or_replace(self, entities).await
+    }
+
+    async fn get_entity(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_entity(self, partition_key, row_key, update_read_statistics).await
+    }
+
+    async fn get_entities(
+        &self,
+        keys: &[(&str, &str)],
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<HashMap<(String, String), Option<TEntity>>, DataWriterError> {
+        MyNoSqlDataWriter::get_entities(self, keys, update_read_statistics).await
+    }
+
+    async fn get_by_partition_key(
+        &self,
+        partition_key: &str,
+        update_read_statistics: Option<UpdateReadStatistics>,
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        MyNoSqlDataWriter::get_by_partition_key(self, partition_key, update_read_statistics).await
+    }
+
+    async fn get_by_partition_key_page(
+        &self,
+        partition_key: &str,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_by_partition_key_page(self, partition_key, skip, limit).await
+    }
+
+    async fn get_highest_row_and_below(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+        max_amount: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_highest_row_and_below(self, partition_key, row_key, max_amount).await
+    }
+
+    async fn get_by_row_key_range(
+        &self,
+        partition_key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_by_row_key_range(self, partition_key, from, to).await
+    }
+
+    async fn get_by_row_key_prefix(
+        &self,
+        partition_key: &str,
+        prefix: &str,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_by_row_key_prefix(self, partition_key, prefix).await
+    }
+
+    async fn get_by_row_key(&self, row_key: &str) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        MyNoSqlDataWriter::get_by_row_key(self, row_key).await
+    }
+
+    async fn get_all(&self) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        MyNoSqlDataWriter::get_all(self).await
+    }
+
+    async fn get_all_page(
+        &self,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::get_all_page(self, skip, limit).await
+    }
+
+    async fn delete_row(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+    ) -> Result<Option<TEntity>, DataWriterError> {
+        MyNoSqlDataWriter::delete_row(self, partition_key, row_key).await
+    }
+
+    async fn delete_partitions(&self, partition_keys: &[&str]) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::delete_partitions(self, partition_keys).await
+    }
+
+    async fn bulk_delete_rows(
+        &self,
+        keys: &[(&str, &str)],
+    ) -> Result<Option<Vec<TEntity>>, DataWriterError> {
+        MyNoSqlDataWriter::bulk_delete_rows(self, keys).await
+    }
+
+    async fn clean_table_and_bulk_insert(
+        &self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::clean_table_and_bulk_insert(self, entities).await
+    }
+
+    async fn clean_partition_and_bulk_insert(
+        &self,
+        partition_key: &str,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        MyNoSqlDataWriter::clean_partition_and_bulk_insert(self, partition_key, entities).await
+    }
+}
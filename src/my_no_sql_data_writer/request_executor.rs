@@ -0,0 +1,162 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use flurl::{FlUrl, FlUrlError, FlUrlResponse};
+use my_logger::LogEventCtx;
+
+use super::{
+    my_no_sql_data_writer::FlUrlExt, writer_response::WriterResponse, DataWriterError,
+    ErrorContext, MyNoSqlWriterSettings, RequestLimiter, RetryPolicy,
+};
+
+// Everything a request to the server goes through: headers, time limit, retries and request limits.
+// Writer shares it with the transactions it creates, so they are sent under the same rules
+#[derive(Clone)]
+pub(crate) struct RequestExecutor {
+    pub settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>,
+    pub headers: Vec<(String, String)>,
+    pub retry_policy: RetryPolicy,
+    pub timeout: Option<Duration>,
+    pub request_limiter: Option<RequestLimiter>,
+}
+
+impl RequestExecutor {
+    pub fn new(settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>) -> Self {
+        Self {
+            settings,
+            headers: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            timeout: None,
+            request_limiter: None,
+        }
+    }
+
+    pub async fn get_fl_url(&self) -> FlUrl {
+        let url = self.settings.get_url().await;
+        FlUrl::new(url).with_headers(&self.headers)
+    }
+
+    pub async fn execute<TFuture: Future<Output = Result<FlUrlResponse, FlUrlError>>>(
+        &self,
+        process_name: &'static str,
+        table_name: &'static str,
+        idempotent: bool,
+        request: impl Fn() -> TFuture,
+    ) -> Result<WriterResponse, DataWriterError> {
+        self.execute_with_timeout(process_name, table_name, idempotent, true, request)
+            .await
+    }
+
+    // Successful body is left on the wire to be read as a stream, so the timeout and the
+    // request limit cover the response headers only
+    pub async fn execute_streaming<TFuture: Future<Output = Result<FlUrlResponse, FlUrlError>>>(
+        &self,
+        process_name: &'static str,
+        table_name: &'static str,
+        idempotent: bool,
+        request: impl Fn() -> TFuture,
+    ) -> Result<WriterResponse, DataWriterError> {
+        self.execute_with_timeout(process_name, table_name, idempotent, false, request)
+            .await
+    }
+
+    async fn execute_with_timeout<TFuture: Future<Output = Result<FlUrlResponse, FlUrlError>>>(
+        &self,
+        process_name: &'static str,
+        table_name: &'static str,
+        idempotent: bool,
+        receive_body: bool,
+        request: impl Fn() -> TFuture,
+    ) -> Result<WriterResponse, DataWriterError> {
+        let ctx = ErrorContext::new(process_name, table_name, self.settings.get_url().await);
+
+        let future = self.execute_with_retries(&ctx, idempotent, receive_body, request);
+
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return future.await,
+        };
+
+        match tokio::time::timeout(timeout, future).await {
+            Ok(result) => result,
+            Err(_) => {
+                let err = DataWriterError::Timeout { ctx };
+
+                my_logger::LOGGER.write_error(
+                    process_name,
+                    format!("{:?}", err),
+                    LogEventCtx::new().add("TableName", table_name),
+                );
+
+                Err(err)
+            }
+        }
+    }
+
+    // Retries are done only for idempotent operations, so repeating a request which reached the server is safe
+    async fn execute_with_retries<TFuture: Future<Output = Result<FlUrlResponse, FlUrlError>>>(
+        &self,
+        ctx: &ErrorContext,
+        idempotent: bool,
+        receive_body: bool,
+        request: impl Fn() -> TFuture,
+    ) -> Result<WriterResponse, DataWriterError> {
+        let max_attempts = if idempotent {
+            self.retry_policy.max_attempts.max(1)
+        } else {
+            1
+        };
+
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            // Slot is taken per attempt, so a request waiting for the retry delay does not hold it
+            let permit = match &self.request_limiter {
+                Some(request_limiter) => Some(request_limiter.acquire().await),
+                None => None,
+            };
+
+            let err = match request().await {
+                Ok(response) => {
+                    if attempt >= max_attempts
+                        || !self
+                            .retry_policy
+                            .is_retryable_status_code(response.get_status_code())
+                    {
+                        let mut response = WriterResponse::new(response, ctx.clone());
+                        // Body is received here so it is covered by the timeout as well
+                        if receive_body || !response.is_ok_result() {
+                            response.get_body().await?;
+                        }
+                        return Ok(response);
+                    }
+
+                    format!("Status code: {}", response.get_status_code())
+                }
+                Err(err) => {
+                    if attempt >= max_attempts {
+                        return Err(DataWriterError::from_fl_url_error(ctx.clone(), err));
+                    }
+
+                    format!("{:?}", err)
+                }
+            };
+
+            drop(permit);
+
+            let delay = self.retry_policy.get_delay(attempt);
+
+            my_logger::LOGGER.write_warning(
+                ctx.operation,
+                format!(
+                    "Attempt {} of {} failed: {}. Retrying in {:?}",
+                    attempt, max_attempts, err, delay
+                ),
+                LogEventCtx::new().add("TableName", ctx.table_name),
+            );
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestLimiterMetrics {
+    pub acquired_amount: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    pub waiting_amount: usize,
+    pub in_flight_amount: usize,
+}
+
+impl RequestLimiterMetrics {
+    pub fn get_avg_wait(&self) -> Duration {
+        if self.acquired_amount == 0 {
+            return Duration::ZERO;
+        }
+
+        // Division is done in u64, so an amount above u32::MAX is not truncated
+        let total_wait_micros = self.total_wait.as_micros() as u64;
+        Duration::from_micros(total_wait_micros / self.acquired_amount)
+    }
+}
+
+#[derive(Default)]
+struct RequestLimiterInner {
+    semaphore: Option<Arc<Semaphore>>,
+    // Requests are spread evenly: every request takes the next free slot
+    min_interval: Option<Duration>,
+    next_slot: Mutex<Option<Instant>>,
+    acquired_amount: AtomicU64,
+    total_wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+    waiting_amount: AtomicUsize,
+    in_flight_amount: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestLimiterParams {
+    pub max_concurrent_requests: Option<usize>,
+    pub max_requests_per_second: Option<u32>,
+}
+
+/// Limits the amount of requests in flight and optionally the amount of requests per second.
+/// Cloned limiter shares the limits, so one limiter can be given to several writers.
+#[derive(Clone)]
+pub struct RequestLimiter {
+    inner: Arc<RequestLimiterInner>,
+}
+
+impl RequestLimiter {
+    pub fn new(params: RequestLimiterParams) -> Self {
+        let semaphore = params
+            .max_concurrent_requests
+            .map(|max_concurrent_requests| {
+                Arc::new(Semaphore::new(max_concurrent_requests.max(1)))
+            });
+
+        let min_interval = params
+            .max_requests_per_second
+            .map(|max_requests_per_second| Duration::from_secs(1) / max_requests_per_second.max(1));
+
+        Self {
+            inner: Arc::new(RequestLimiterInner {
+                semaphore,
+                min_interval,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn get_metrics(&self) -> RequestLimiterMetrics {
+        let inner = &self.inner;
+
+        RequestLimiterMetrics {
+            acquired_amount: inner.acquired_amount.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(inner.total_wait_micros.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(inner.max_wait_micros.load(Ordering::Relaxed)),
+            waiting_amount: inner.waiting_amount.load(Ordering::Relaxed),
+            in_flight_amount: inner.in_flight_amount.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Waits for a free slot. The request is counted as in flight until the permit is dropped.
+    pub async fn acquire(&self) -> RequestPermit {
+        let inner = &self.inner;
+        let started = Instant::now();
+
+        let waiting = WaitingGuard::new(&inner.waiting_amount);
+
+        let semaphore_permit = match &inner.semaphore {
+            // Semaphore is never closed
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        if let Some(slot) = self.take_rate_slot() {
+            tokio::time::sleep_until(slot).await;
+        }
+
+        drop(waiting);
+
+        let wait_micros = started.elapsed().as_micros() as u64;
+        inner.acquired_amount.fetch_add(1, Ordering::Relaxed);
+        inner
+            .total_wait_micros
+            .fetch_add(wait_micros, Ordering::Relaxed);
+        inner
+            .max_wait_micros
+            .fetch_max(wait_micros, Ordering::Relaxed);
+        inner.in_flight_amount.fetch_add(1, Ordering::Relaxed);
+
+        RequestPermit {
+            limiter: self.inner.clone(),
+            _semaphore_permit: semaphore_permit,
+        }
+    }
+
+    fn take_rate_slot(&self) -> Option<Instant> {
+        let min_interval = self.inner.min_interval?;
+        let now = Instant::now();
+
+        let mut next_slot = self.inner.next_slot.lock().unwrap();
+
+        let slot = match *next_slot {
+            Some(next_slot) if next_slot > now => next_slot,
+            _ => now,
+        };
+
+        *next_slot = Some(slot + min_interval);
+
+        Some(slot)
+    }
+}
+
+// Waiting future can be dropped by a timeout, so the counter is decremented on drop
+struct WaitingGuard<'s> {
+    waiting_amount: &'s AtomicUsize,
+}
+
+impl<'s> WaitingGuard<'s> {
+    fn new(waiting_amount: &'s AtomicUsize) -> Self {
+        waiting_amount.fetch_add(1, Ordering::Relaxed);
+        Self { waiting_amount }
+    }
+}
+
+impl<'s> Drop for WaitingGuard<'s> {
+    fn drop(&mut self) {
+        self.waiting_amount.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct RequestPermit {
+    limiter: Arc<RequestLimiterInner>,
+    _semaphore_permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.limiter
+            .in_flight_amount
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{RequestLimiter, RequestLimiterMetrics, RequestLimiterParams};
+
+    #[test]
+    fn test_avg_wait() {
+        let metrics = RequestLimiterMetrics::default();
+        assert_eq!(metrics.get_avg_wait(), Duration::ZERO);
+
+        let metrics = RequestLimiterMetrics {
+            acquired_amount: 4,
+            total_wait: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert_eq!(metrics.get_avg_wait(), Duration::from_micros(2500));
+
+        // Amount does not fit into u32
+        let metrics = RequestLimiterMetrics {
+            acquired_amount: 1 << 32,
+            total_wait: Duration::from_secs(1 << 32),
+            ..Default::default()
+        };
+        assert_eq!(metrics.get_avg_wait(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_limited() {
+        let limiter = RequestLimiter::new(RequestLimiterParams {
+            max_concurrent_requests: Some(2),
+            max_requests_per_second: None,
+        });
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = Vec::new();
+
+        for _ in 0..5 {
+            let limiter = limiter.clone();
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+
+            tasks.push(tokio::spawn(async move {
+                let _permit = limiter.acquire().await;
+
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+
+        let metrics = limiter.get_metrics();
+        assert_eq!(metrics.acquired_amount, 5);
+        assert_eq!(metrics.in_flight_amount, 0);
+        assert_eq!(metrics.waiting_amount, 0);
+        assert!(metrics.max_wait >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_second_are_limited() {
+        let limiter = RequestLimiter::new(RequestLimiterParams {
+            max_concurrent_requests: None,
+            max_requests_per_second: Some(20),
+        });
+
+        let started = tokio::time::Instant::now();
+
+        for _ in 0..3 {
+            let _permit = limiter.acquire().await;
+        }
+
+        // First request goes immediately, the next ones wait for 50ms slots
+        assert!(started.elapsed() >= Duration::from_millis(95));
+    }
+}
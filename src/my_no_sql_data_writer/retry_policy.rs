@@ -0,0 +1,125 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use super::DataWriterError;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub retryable_status_codes: Vec<u16>,
+    // Insert is not idempotent - a retry after a lost response ends up with RecordAlreadyExists
+    pub retry_insert_entity: bool,
+}
+
+impl RetryPolicy {
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_retryable_status_code(&self, status_code: u16) -> bool {
+        self.retryable_status_codes.contains(&status_code)
+    }
+
+    // Transport failures and timeouts are worth repeating.
+    // Server errors only with the listed status codes. Everything else is a definitive answer
+    pub fn is_retryable_error(&self, err: &DataWriterError) -> bool {
+        match err {
+            DataWriterError::FlUrlError { .. } => true,
+            DataWriterError::HyperError { .. } => true,
+            DataWriterError::Timeout { .. } => true,
+            DataWriterError::ServerError { status, .. } => self.is_retryable_status_code(*status),
+            _ => false,
+        }
+    }
+
+    // Exponential backoff with jitter. attempt is 1-based number of the failed attempt
+    pub fn get_delay(&self, attempt: usize) -> Duration {
+        let shift = attempt.saturating_sub(1).min(16) as u32;
+
+        let delay = self
+            .initial_delay
+            .saturating_mul(1u32 << shift)
+            .min(self.max_delay);
+
+        let half = delay.as_micros() as u64 / 2;
+
+        if half == 0 {
+            return delay;
+        }
+
+        Duration::from_micros(half + get_random_u64() % (half + 1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            retryable_status_codes: vec![502, 503, 504],
+            retry_insert_entity: false,
+        }
+    }
+}
+
+fn get_random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+    use crate::{DataWriterError, ErrorContext};
+
+    #[test]
+    fn test_delay_is_growing_and_capped() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            ..Default::default()
+        };
+
+        let delay = policy.get_delay(1);
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+
+        let delay = policy.get_delay(2);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+
+        let delay = policy.get_delay(10);
+        assert!(delay >= Duration::from_millis(150) && delay <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_retryable_errors_follow_status_codes() {
+        let server_error = DataWriterError::ServerError {
+            ctx: ErrorContext::default(),
+            status: 500,
+            body: String::new(),
+        };
+
+        assert!(!RetryPolicy::default().is_retryable_error(&server_error));
+        assert!(!server_error.is_retryable());
+
+        let policy = RetryPolicy {
+            retryable_status_codes: vec![500],
+            ..Default::default()
+        };
+        assert!(policy.is_retryable_error(&server_error));
+
+        let timeout = DataWriterError::Timeout {
+            ctx: ErrorContext::default(),
+        };
+        assert!(policy.is_retryable_error(&timeout));
+    }
+}
@@ -0,0 +1,1124 @@
+use std::{
+    ffi::OsString,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+use my_logger::LogEventCtx;
+use my_no_sql_server_abstractions::MyNoSqlEntity;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
+    task::JoinHandle,
+};
+
+use super::{DataWriterError, ErrorContext, MyNoSqlDataWriterTrait, RetryPolicy};
+
+const SCAN_BLOCK_SIZE: usize = 64 * 1024;
+
+// Replayed records are sent again after a crash unless the position is saved. Records are writes
+// of whole rows, so sending them again in the same order gives the same result
+const POSITION_SAVE_INTERVAL: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct SpoolParams {
+    // Every table gets its own file in the directory
+    pub directory: PathBuf,
+    // Bytes on disk per table. Writes which do not fit are rejected with SpoolIsFull
+    pub max_spool_size: u64,
+    // Bytes of the rejected records per table. When it is full, the replay stops at the next
+    // record to be rejected until the <table>.spool.rejected file is removed
+    pub max_rejected_size: u64,
+    pub replay_period: Duration,
+    // Decides which failures mean the server is not available. Should match the writer policy
+    pub retry_policy: RetryPolicy,
+}
+
+impl SpoolParams {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_spool_size: 256 * 1024 * 1024,
+            max_rejected_size: 16 * 1024 * 1024,
+            replay_period: Duration::from_secs(1),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpoolBacklog {
+    pub records_amount: usize,
+    // Bytes of the records which are not replayed yet
+    pub size: u64,
+    // Records the server can never accept. They are kept in the <table>.spool.rejected file
+    pub rejected_amount: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum SpoolRecord<TEntities> {
+    InsertOrReplace {
+        entities: TEntities,
+    },
+    DeleteRow {
+        #[serde(rename = "partitionKey")]
+        partition_key: String,
+        #[serde(rename = "rowKey")]
+        row_key: String,
+    },
+}
+
+#[derive(Default)]
+struct LinesScan {
+    // Size up to the end of the last complete line
+    complete_size: u64,
+    file_size: u64,
+    // Complete lines which end after the given offset
+    lines_amount: usize,
+}
+
+// Records are appended to the file as json lines. The position file keeps the offset of the first
+// record which is not replayed yet, so after a restart the replayed records are not sent again.
+// It is saved every POSITION_SAVE_INTERVAL records and when a replay stops.
+// Files are read block by block or record by record, so a big backlog is never loaded at once
+struct SpoolFile {
+    table_name: &'static str,
+    directory: PathBuf,
+    path: PathBuf,
+    position_path: PathBuf,
+    rejected_path: PathBuf,
+    position: u64,
+    saved_position: u64,
+    size: u64,
+    records_amount: usize,
+    rejected_size: u64,
+    rejected_amount: usize,
+}
+
+impl SpoolFile {
+    async fn open(directory: &Path, table_name: &'static str) -> Result<Self, DataWriterError> {
+        let mut result = Self {
+            table_name,
+            directory: directory.to_path_buf(),
+            path: directory.join(format!("{}.spool", table_name)),
+            position_path: directory.join(format!("{}.spool.position", table_name)),
+            rejected_path: directory.join(format!("{}.spool.rejected", table_name)),
+            position: 0,
+            saved_position: 0,
+            size: 0,
+            records_amount: 0,
+            rejected_size: 0,
+            rejected_amount: 0,
+        };
+
+        tokio::fs::create_dir_all(directory)
+            .await
+            .map_err(|err| result.io_error("spool_open", directory, err))?;
+
+        result.scan_rejected("spool_open").await?;
+
+        let position = match tokio::fs::read_to_string(&result.position_path).await {
+            Ok(position) => position.trim().parse::<u64>().unwrap_or(0),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(result.io_error("spool_open", &result.position_path, err)),
+        };
+
+        let scan = match scan_lines(&result.path, position).await {
+            Ok(Some(scan)) => scan,
+            Ok(None) => return Ok(result),
+            Err(err) => return Err(result.io_error("spool_open", &result.path, err)),
+        };
+
+        // Record which was being written during a crash is not complete, so it is cut off
+        if scan.complete_size < scan.file_size {
+            result.truncate(scan.complete_size).await?;
+        }
+
+        result.position = position.min(scan.complete_size);
+        result.saved_position = result.position;
+        result.size = scan.complete_size;
+        result.records_amount = scan.lines_amount;
+
+        Ok(result)
+    }
+
+    fn get_backlog_size(&self) -> u64 {
+        self.size - self.position
+    }
+
+    // Reader which starts at the first record which is not replayed yet
+    async fn open_reader(
+        &self,
+        process_name: &'static str,
+    ) -> Result<BufReader<File>, DataWriterError> {
+        let mut file = File::open(&self.path)
+            .await
+            .map_err(|err| self.io_error(process_name, &self.path, err))?;
+
+        file.seek(SeekFrom::Start(self.position))
+            .await
+            .map_err(|err| self.io_error(process_name, &self.path, err))?;
+
+        Ok(BufReader::new(file))
+    }
+
+    async fn append(&mut self, line: &[u8]) -> Result<(), DataWriterError> {
+        let mut file = open_for_append(&self.path)
+            .await
+            .map_err(|err| self.io_error("spool_append", &self.path, err))?;
+
+        append_line(&mut file, self.size, line)
+            .await
+            .map_err(|err| self.io_error("spool_append", &self.path, err))?;
+
+        self.size += line.len() as u64;
+        self.records_amount += 1;
+
+        Ok(())
+    }
+
+    // Rejected file may be removed or cut while the writer is running, so it is read again
+    // before the writer reports that it is full
+    async fn scan_rejected(&mut self, process_name: &'static str) -> Result<(), DataWriterError> {
+        let rejected = scan_lines(&self.rejected_path, 0)
+            .await
+            .map_err(|err| self.io_error(process_name, &self.rejected_path, err))?
+            .unwrap_or_default();
+
+        // Record which was being written during a crash is cut off by the next append
+        self.rejected_size = rejected.complete_size;
+        self.rejected_amount = rejected.lines_amount;
+
+        Ok(())
+    }
+
+    // Record is kept for investigation instead of being dropped. It is moved before the position
+    // goes past it, so a crash in between can only leave a copy of it in the spool.
+    // File is opened once per replay and is given back in rejected_file
+    async fn reject(
+        &mut self,
+        rejected_file: &mut Option<File>,
+        line: &[u8],
+        max_rejected_size: u64,
+    ) -> Result<(), DataWriterError> {
+        if self.rejected_size + line.len() as u64 > max_rejected_size {
+            *rejected_file = None;
+            self.scan_rejected("spool_reject").await?;
+
+            if self.rejected_size + line.len() as u64 > max_rejected_size {
+                return Err(DataWriterError::SpoolIsFull {
+                    ctx: ErrorContext::new("spool_reject", self.table_name, String::new()),
+                    message: format!(
+                        "{} of {} bytes of {} are used",
+                        self.rejected_size,
+                        max_rejected_size,
+                        self.rejected_path.display()
+                    ),
+                });
+            }
+        }
+
+        let file = match rejected_file.take() {
+            Some(file) => file,
+            None => open_for_append(&self.rejected_path)
+                .await
+                .map_err(|err| self.io_error("spool_reject", &self.rejected_path, err))?,
+        };
+
+        let file = rejected_file.insert(file);
+
+        append_line(file, self.rejected_size, line)
+            .await
+            .map_err(|err| self.io_error("spool_reject", &self.rejected_path, err))?;
+
+        self.rejected_size += line.len() as u64;
+        self.rejected_amount += 1;
+
+        Ok(())
+    }
+
+    // Marks the next record as replayed. The position is saved with save_position
+    fn consume(&mut self, position: u64) {
+        self.position = position;
+        self.records_amount -= 1;
+    }
+
+    async fn save_position(&mut self) -> Result<(), DataWriterError> {
+        if self.saved_position == self.position {
+            return Ok(());
+        }
+
+        replace_file(&self.position_path, self.position.to_string().as_bytes())
+            .await
+            .map_err(|err| self.io_error("spool_replay", &self.position_path, err))?;
+
+        self.saved_position = self.position;
+
+        Ok(())
+    }
+
+    async fn clear(&mut self) -> Result<(), DataWriterError> {
+        // Position goes first: without it the whole file is replayed again, which is safe
+        for path in [&self.position_path, &self.path] {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(self.io_error("spool_clear", path, err)),
+            }
+        }
+
+        self.position = 0;
+        self.saved_position = 0;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    // Replayed records are cut off, so the file does not grow while the server is flapping
+    async fn compact(&mut self) -> Result<(), DataWriterError> {
+        let tmp_path = get_tmp_path(&self.path);
+
+        let mut reader = self.open_reader("spool_compact").await?;
+
+        let mut tmp_file = File::create(&tmp_path)
+            .await
+            .map_err(|err| self.io_error("spool_compact", &tmp_path, err))?;
+
+        let copied = tokio::io::copy_buf(&mut reader, &mut tmp_file).await;
+
+        if let Err(err) = copied {
+            return Err(self.io_error("spool_compact", &tmp_path, err));
+        }
+
+        // Data must be on disk before the rename makes it the spool
+        tmp_file
+            .sync_all()
+            .await
+            .map_err(|err| self.io_error("spool_compact", &tmp_path, err))?;
+
+        // Position is reset before the rename, so a crash in between only repeats replayed records
+        replace_file(&self.position_path, b"0")
+            .await
+            .map_err(|err| self.io_error("spool_compact", &self.position_path, err))?;
+
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .map_err(|err| self.io_error("spool_compact", &self.path, err))?;
+
+        sync_directory(&self.directory)
+            .await
+            .map_err(|err| self.io_error("spool_compact", &self.directory, err))?;
+
+        self.size -= self.position;
+        self.position = 0;
+        self.saved_position = 0;
+
+        Ok(())
+    }
+
+    async fn truncate(&self, size: u64) -> Result<(), DataWriterError> {
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .await
+            .map_err(|err| self.io_error("spool_open", &self.path, err))?;
+
+        file.set_len(size)
+            .await
+            .map_err(|err| self.io_error("spool_open", &self.path, err))
+    }
+
+    fn io_error(
+        &self,
+        process_name: &'static str,
+        path: &Path,
+        err: std::io::Error,
+    ) -> DataWriterError {
+        DataWriterError::SpoolIoError {
+            ctx: ErrorContext::new(process_name, self.table_name, String::new()),
+            path: path.display().to_string(),
+            err,
+        }
+    }
+}
+
+// Reads the file block by block. None if there is no file
+async fn scan_lines(path: &Path, from: u64) -> std::io::Result<Option<LinesScan>> {
+    let mut file = match File::open(path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut scan = LinesScan::default();
+    let mut block = vec![0u8; SCAN_BLOCK_SIZE];
+
+    loop {
+        let read = file.read(&mut block).await?;
+
+        if read == 0 {
+            return Ok(Some(scan));
+        }
+
+        for (index, b) in block[..read].iter().enumerate() {
+            if *b == b'\n' {
+                scan.complete_size = scan.file_size + index as u64 + 1;
+
+                if scan.complete_size > from {
+                    scan.lines_amount += 1;
+                }
+            }
+        }
+
+        scan.file_size += read as u64;
+    }
+}
+
+async fn open_for_append(path: &Path) -> std::io::Result<File> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+// Line is reported as written only when it is on disk. Size is the end of the last line which
+// is reported as written: whatever is after it is left by an append which was cancelled or
+// failed, so it is cut off instead of being glued to the line
+async fn append_line(file: &mut File, size: u64, line: &[u8]) -> std::io::Result<()> {
+    if file.metadata().await?.len() > size {
+        file.set_len(size).await?;
+    }
+
+    let result = match file.write_all(line).await {
+        Ok(()) => file.sync_data().await,
+        Err(err) => Err(err),
+    };
+
+    if result.is_err() {
+        // Part of the line must not be glued to the next one
+        let _ = file.set_len(size).await;
+    }
+
+    result
+}
+
+// Content is written next to the file and renamed over it, so the file is never half-written
+async fn replace_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = get_tmp_path(path);
+
+    let mut file = File::create(&tmp_path).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+
+    tokio::fs::rename(&tmp_path, path).await
+}
+
+fn get_tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = OsString::from(path.as_os_str());
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
+
+// Makes a rename durable. Directories can not be opened as files on windows
+#[cfg(unix)]
+async fn sync_directory(directory: &Path) -> std::io::Result<()> {
+    File::open(directory).await?.sync_all().await
+}
+
+#[cfg(not(unix))]
+async fn sync_directory(_directory: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Records the server can never accept as they are. Repeating them would block the rest forever
+fn is_invalid_record(err: &DataWriterError) -> bool {
+    matches!(
+        err,
+        DataWriterError::RequiredEntityFieldIsMissing { .. }
+            | DataWriterError::ServerCouldNotParseJson { .. }
+            | DataWriterError::SerializationFailed { .. }
+    )
+}
+
+struct SpoolingDataWriterInner<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+{
+    writer: Arc<dyn MyNoSqlDataWriterTrait<TEntity> + Send + Sync>,
+    spool: tokio::sync::Mutex<SpoolFile>,
+    max_spool_size: u64,
+    max_rejected_size: u64,
+    retry_policy: RetryPolicy,
+    // Copy of the backlog which can be read without waiting for a write in progress
+    records_amount: AtomicUsize,
+    backlog_size: AtomicU64,
+    rejected_amount: AtomicUsize,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>
+    SpoolingDataWriterInner<TEntity>
+{
+    fn update_backlog(&self, spool: &SpoolFile) {
+        self.records_amount
+            .store(spool.records_amount, Ordering::Relaxed);
+        self.backlog_size
+            .store(spool.get_backlog_size(), Ordering::Relaxed);
+        self.rejected_amount
+            .store(spool.rejected_amount, Ordering::Relaxed);
+    }
+
+    async fn send<TEntities: AsRef<[TEntity]>>(
+        &self,
+        record: &SpoolRecord<TEntities>,
+    ) -> Result<(), DataWriterError> {
+        match record {
+            SpoolRecord::InsertOrReplace { entities } => {
+                self.writer.bulk_insert_or_replace(entities.as_ref()).await
+            }
+            SpoolRecord::DeleteRow {
+                partition_key,
+                row_key,
+            } => self
+                .writer
+                .delete_row(partition_key, row_key)
+                .await
+                .map(|_| ()),
+        }
+    }
+
+    async fn write<TEntities: AsRef<[TEntity]> + Serialize>(
+        &self,
+        process_name: &'static str,
+        record: SpoolRecord<TEntities>,
+    ) -> Result<(), DataWriterError> {
+        let mut spool = self.spool.lock().await;
+
+        // While there is a backlog, new writes go behind it to keep the order
+        if spool.records_amount == 0 {
+            let err = match self.send(&record).await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+
+            if !self.retry_policy.is_retryable_error(&err) {
+                return Err(err);
+            }
+
+            my_logger::LOGGER.write_warning(
+                "SpoolingDataWriter",
+                format!("Server is not available. Writes are spooled: {}", err),
+                LogEventCtx::new().add("TableName", TEntity::TABLE_NAME),
+            );
+        }
+
+        let ctx = ErrorContext::new(process_name, TEntity::TABLE_NAME, String::new());
+
+        let mut line =
+            serde_json::to_vec(&record).map_err(|err| DataWriterError::SerializationFailed {
+                ctx: ctx.clone(),
+                err,
+            })?;
+        line.push(b'\n');
+
+        if spool.size + line.len() as u64 > self.max_spool_size {
+            return Err(DataWriterError::SpoolIsFull {
+                ctx,
+                message: format!("{} of {} bytes are used", spool.size, self.max_spool_size),
+            });
+        }
+
+        spool.append(&line).await?;
+        self.update_backlog(&spool);
+
+        Ok(())
+    }
+
+    // Stops at the first record which fails for a reason other than the record itself,
+    // e.g. the server is not available or the table does not exist. The record stays in the spool
+    async fn replay(&self) -> Result<(), DataWriterError> {
+        let mut spool = self.spool.lock().await;
+
+        if spool.records_amount == 0 {
+            return Ok(());
+        }
+
+        let result = self.replay_records(&mut spool).await;
+
+        if spool.records_amount == 0 {
+            spool.clear().await?;
+        } else if spool.position > spool.size / 2 {
+            spool.compact().await?;
+        } else {
+            spool.save_position().await?;
+        }
+
+        self.update_backlog(&spool);
+
+        result
+    }
+
+    async fn replay_records(&self, spool: &mut SpoolFile) -> Result<(), DataWriterError> {
+        let mut reader = spool.open_reader("spool_replay").await?;
+        let mut rejected_file = None;
+        let mut line = Vec::new();
+        let mut unsaved_amount = 0;
+
+        while spool.records_amount > 0 {
+            line.clear();
+
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(|err| spool.io_error("spool_replay", &spool.path, err))?;
+
+            // Nothing is appended while the spool is locked, so only complete records are there
+            if read == 0 || line.last() != Some(&b'\n') {
+                return Ok(());
+            }
+
+            let reject_reason =
+                match serde_json::from_slice::<SpoolRecord<Vec<TEntity>>>(&line[..read - 1]) {
+                    Ok(record) => match self.send(&record).await {
+                        Ok(()) => None,
+                        Err(err) if is_invalid_record(&err) => Some(err.to_string()),
+                        Err(err) => return Err(err),
+                    },
+                    Err(err) => Some(format!("Corrupted record: {}", err)),
+                };
+
+            if let Some(reject_reason) = reject_reason {
+                spool
+                    .reject(&mut rejected_file, &line, self.max_rejected_size)
+                    .await?;
+
+                write_spool_error::<TEntity>(format!(
+                    "Spooled record is moved to {}: {}",
+                    spool.rejected_path.display(),
+                    reject_reason
+                ));
+            }
+
+            let position = spool.position + read as u64;
+            spool.consume(position);
+            self.update_backlog(spool);
+
+            unsaved_amount += 1;
+
+            if unsaved_amount >= POSITION_SAVE_INTERVAL {
+                spool.save_position().await?;
+                unsaved_amount = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes which fail because the server is not available are appended to a file per table
+/// and replayed in the same order every `replay_period` once the server responds again.
+/// While there is a backlog, new writes are appended to it as well.
+///
+/// Writes go one by one to keep the order. Errors which are a definitive answer of the server
+/// are returned as is. When the spool reaches `max_spool_size`, writes fail with `SpoolIsFull`.
+/// A directory must not be shared by several writers of the same table.
+///
+/// Spooled records which the server rejects as invalid are moved to the `<table>.spool.rejected`
+/// file and counted in the backlog. When the file reaches `max_rejected_size`, the replay stops
+/// with `SpoolIsFull` until the file is removed. Other definitive errors, e.g. `TableNotFound`,
+/// stop the replay and the record is sent again with the next one.
+pub struct SpoolingDataWriter<
+    TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static,
+> {
+    inner: Arc<SpoolingDataWriterInner<TEntity>>,
+    replay_timer: JoinHandle<()>,
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static>
+    SpoolingDataWriter<TEntity>
+{
+    /// Opens the spool of the table left by a previous run. Its records are replayed first.
+    pub async fn new(
+        writer: Arc<dyn MyNoSqlDataWriterTrait<TEntity> + Send + Sync>,
+        params: SpoolParams,
+    ) -> Result<Self, DataWriterError> {
+        let spool = SpoolFile::open(&params.directory, TEntity::TABLE_NAME).await?;
+
+        let inner = Arc::new(SpoolingDataWriterInner {
+            writer,
+            records_amount: AtomicUsize::new(spool.records_amount),
+            backlog_size: AtomicU64::new(spool.get_backlog_size()),
+            rejected_amount: AtomicUsize::new(spool.rejected_amount),
+            spool: tokio::sync::Mutex::new(spool),
+            max_spool_size: params.max_spool_size,
+            max_rejected_size: params.max_rejected_size,
+            retry_policy: params.retry_policy,
+        });
+
+        let replay_timer = tokio::spawn(replay_by_timer(
+            Arc::downgrade(&inner),
+            params.replay_period,
+        ));
+
+        Ok(Self {
+            inner,
+            replay_timer,
+        })
+    }
+
+    pub fn get_backlog(&self) -> SpoolBacklog {
+        SpoolBacklog {
+            records_amount: self.inner.records_amount.load(Ordering::Relaxed),
+            size: self.inner.backlog_size.load(Ordering::Relaxed),
+            rejected_amount: self.inner.rejected_amount.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn insert_or_replace_entity(&self, entity: &TEntity) -> Result<(), DataWriterError> {
+        let record = SpoolRecord::InsertOrReplace {
+            entities: std::slice::from_ref(entity),
+        };
+
+        self.inner.write("spool_insert_or_replace", record).await
+    }
+
+    pub async fn bulk_insert_or_replace(
+        &self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        if entities.is_empty() {
+            return Ok(());
+        }
+
+        let record = SpoolRecord::InsertOrReplace { entities };
+
+        self.inner
+            .write("spool_bulk_insert_or_replace", record)
+            .await
+    }
+
+    /// Unlike the writer, the deleted entity is not returned since the delete may be spooled.
+    pub async fn delete_row(
+        &self,
+        partition_key: &str,
+        row_key: &str,
+    ) -> Result<(), DataWriterError> {
+        let record: SpoolRecord<&[TEntity]> = SpoolRecord::DeleteRow {
+            partition_key: partition_key.to_string(),
+            row_key: row_key.to_string(),
+        };
+
+        self.inner.write("spool_delete_row", record).await
+    }
+
+    /// Sends the backlog without waiting for the timer. Stops at the first failure
+    /// which is not caused by the record itself.
+    pub async fn replay(&self) -> Result<(), DataWriterError> {
+        self.inner.replay().await
+    }
+}
+
+impl<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize + 'static> Drop
+    for SpoolingDataWriter<TEntity>
+{
+    fn drop(&mut self) {
+        // Backlog stays on disk and is replayed by the next writer of the table
+        self.replay_timer.abort();
+    }
+}
+
+// Holds a weak reference, so the timer does not keep a dropped writer alive
+async fn replay_by_timer<TEntity: MyNoSqlEntity + Sync + Send + DeserializeOwned + Serialize>(
+    inner: Weak<SpoolingDataWriterInner<TEntity>>,
+    replay_period: Duration,
+) {
+    loop {
+        tokio::time::sleep(replay_period).await;
+
+        let inner = match inner.upgrade() {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        // Server is still not available - the backlog is visible with get_backlog
+        if let Err(err) = inner.replay().await {
+            if !inner.retry_policy.is_retryable_error(&err) {
+                write_spool_error::<TEntity>(format!("Failed to replay spool: {}", err));
+            }
+        }
+    }
+}
+
+fn write_spool_error<TEntity: MyNoSqlEntity>(message: String) {
+    my_logger::LOGGER.write_error(
+        "SpoolingDataWriter",
+        message,
+        LogEventCtx::new().add("TableName", TEntity::TABLE_NAME),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, time::Duration};
+
+    use super::{SpoolParams, SpoolRecord, SpoolingDataWriter};
+    use crate::{
+        test_fixtures::{create_writer_with_retry_policy, TestEntity},
+        DataWriterError, MockFault, MockMyNoSqlServer, MyNoSqlDataWriter, RetryPolicy,
+    };
+
+    fn create_writer(server: &MockMyNoSqlServer) -> Arc<MyNoSqlDataWriter<TestEntity>> {
+        Arc::new(create_writer_with_retry_policy(
+            server,
+            RetryPolicy::no_retries(),
+        ))
+    }
+
+    fn create_spool_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("my-no-sql-spool-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn get_record_size(entity: &TestEntity) -> u64 {
+        let record = SpoolRecord::InsertOrReplace {
+            entities: std::slice::from_ref(entity),
+        };
+
+        // Record goes as a json line
+        serde_json::to_vec(&record).unwrap().len() as u64 + 1
+    }
+
+    fn create_unavailable_fault() -> MockFault {
+        MockFault::ServerError {
+            status: 503,
+            body: "Restarting".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_spooled_and_replayed_in_order() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("order");
+        let writer = create_writer(&server);
+
+        let spooling_writer = SpoolingDataWriter::new(
+            writer.clone(),
+            SpoolParams {
+                replay_period: Duration::from_secs(60),
+                ..SpoolParams::new(&directory)
+            },
+        )
+        .await
+        .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk1", 2))
+            .await
+            .unwrap();
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk2", 3))
+            .await
+            .unwrap();
+        spooling_writer.delete_row("pk", "rk2").await.unwrap();
+
+        assert_eq!(spooling_writer.get_backlog().records_amount, 4);
+        assert_eq!(server.get_rows_amount("test"), 0);
+
+        spooling_writer.replay().await.unwrap();
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 0);
+        assert_eq!(backlog.size, 0);
+        assert!(!directory.join("test.spool").exists());
+
+        assert_eq!(server.get_rows_amount("test"), 1);
+        let entity = writer.get_entity("pk", "rk1", None).await.unwrap();
+        assert_eq!(entity.unwrap().value, 2);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_spool_is_bounded_and_survives_restart() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("restart");
+
+        // Records are of the same size, so only two of them fit
+        let record_size = get_record_size(&TestEntity::new("pk", "rk1", 1));
+
+        let params = SpoolParams {
+            max_spool_size: record_size * 5 / 2,
+            replay_period: Duration::from_secs(60),
+            ..SpoolParams::new(&directory)
+        };
+
+        let spooling_writer = SpoolingDataWriter::new(create_writer(&server), params.clone())
+            .await
+            .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk2", 2))
+            .await
+            .unwrap();
+
+        let result = spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk3", 3))
+            .await;
+        assert!(matches!(result, Err(DataWriterError::SpoolIsFull { .. })));
+        assert_eq!(spooling_writer.get_backlog().records_amount, 2);
+
+        drop(spooling_writer);
+
+        let spooling_writer = SpoolingDataWriter::new(create_writer(&server), params)
+            .await
+            .unwrap();
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 2);
+        assert_eq!(backlog.size, record_size * 2);
+
+        spooling_writer.replay().await.unwrap();
+
+        assert_eq!(spooling_writer.get_backlog().records_amount, 0);
+        assert_eq!(server.get_rows_amount("test"), 2);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_records_are_rejected_and_config_errors_stop_replay() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("rejected");
+
+        let spooling_writer = SpoolingDataWriter::new(
+            create_writer(&server),
+            SpoolParams {
+                replay_period: Duration::from_secs(60),
+                ..SpoolParams::new(&directory)
+            },
+        )
+        .await
+        .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        for (row_key, value) in [("rk1", 1), ("rk2", 2), ("rk3", 3)] {
+            spooling_writer
+                .insert_or_replace_entity(&TestEntity::new("pk", row_key, value))
+                .await
+                .unwrap();
+        }
+
+        // Table which is not there is not a problem of the record, so it is kept
+        server.inject_fault(MockFault::Reject {
+            status: 400,
+            reason: "TableNotFound".to_string(),
+        });
+
+        let result = spooling_writer.replay().await;
+        assert!(matches!(result, Err(DataWriterError::TableNotFound { .. })));
+        assert_eq!(spooling_writer.get_backlog().records_amount, 3);
+
+        server.inject_fault(MockFault::Reject {
+            status: 400,
+            reason: "RequiredEntityFieldIsMissing".to_string(),
+        });
+
+        spooling_writer.replay().await.unwrap();
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 0);
+        assert_eq!(backlog.rejected_amount, 1);
+        assert_eq!(server.get_rows_amount("test"), 2);
+
+        let rejected = std::fs::read_to_string(directory.join("test.spool.rejected")).unwrap();
+        assert_eq!(rejected.lines().count(), 1);
+        assert!(rejected.contains("rk1"));
+
+        drop(spooling_writer);
+
+        // Rejected records are counted after a restart as well
+        let spooling_writer =
+            SpoolingDataWriter::new(create_writer(&server), SpoolParams::new(&directory))
+                .await
+                .unwrap();
+        assert_eq!(spooling_writer.get_backlog().rejected_amount, 1);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_position_is_saved_when_replay_stops() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("position");
+        let record_size = get_record_size(&TestEntity::new("pk", "rk1", 1));
+
+        let params = SpoolParams {
+            replay_period: Duration::from_secs(60),
+            ..SpoolParams::new(&directory)
+        };
+
+        let spooling_writer = SpoolingDataWriter::new(create_writer(&server), params.clone())
+            .await
+            .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        for (row_key, value) in [("rk1", 1), ("rk2", 2), ("rk3", 3)] {
+            spooling_writer
+                .insert_or_replace_entity(&TestEntity::new("pk", row_key, value))
+                .await
+                .unwrap();
+        }
+
+        // First record is written and the server goes down again on the second one
+        server.inject_fault_for_path("/Bulk/InsertOrReplace", MockFault::Delay(Duration::ZERO));
+        server.inject_fault_for_path("/Bulk/InsertOrReplace", create_unavailable_fault());
+
+        let result = spooling_writer.replay().await;
+        assert!(matches!(result, Err(DataWriterError::ServerError { .. })));
+        assert_eq!(spooling_writer.get_backlog().records_amount, 2);
+
+        let position = std::fs::read_to_string(directory.join("test.spool.position")).unwrap();
+        assert_eq!(position, record_size.to_string());
+
+        drop(spooling_writer);
+
+        let spooling_writer = SpoolingDataWriter::new(create_writer(&server), params)
+            .await
+            .unwrap();
+        assert_eq!(spooling_writer.get_backlog().records_amount, 2);
+
+        spooling_writer.replay().await.unwrap();
+        assert_eq!(server.get_rows_amount("test"), 3);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_part_of_cancelled_append_is_cut_off() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("cancelled");
+
+        let spooling_writer = SpoolingDataWriter::new(
+            create_writer(&server),
+            SpoolParams {
+                replay_period: Duration::from_secs(60),
+                ..SpoolParams::new(&directory)
+            },
+        )
+        .await
+        .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk1", 1))
+            .await
+            .unwrap();
+
+        // What an append cancelled in the middle of the line leaves behind
+        {
+            use std::io::Write;
+
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(directory.join("test.spool"))
+                .unwrap();
+            file.write_all(br#"{"type":"InsertOrRep"#).unwrap();
+        }
+
+        spooling_writer
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk2", 2))
+            .await
+            .unwrap();
+
+        spooling_writer.replay().await.unwrap();
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 0);
+        assert_eq!(backlog.rejected_amount, 0);
+        assert_eq!(server.get_rows_amount("test"), 2);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_file_is_limited() {
+        let server = MockMyNoSqlServer::start().await;
+        let directory = create_spool_directory("rejected-limit");
+
+        // Rejected record goes to the file as is, so only one of them fits
+        let record_size = get_record_size(&TestEntity::new("pk", "rk1", 1));
+
+        let spooling_writer = SpoolingDataWriter::new(
+            create_writer(&server),
+            SpoolParams {
+                max_rejected_size: record_size,
+                replay_period: Duration::from_secs(60),
+                ..SpoolParams::new(&directory)
+            },
+        )
+        .await
+        .unwrap();
+
+        server.inject_fault(create_unavailable_fault());
+
+        for (row_key, value) in [("rk1", 1), ("rk2", 2), ("rk3", 3)] {
+            spooling_writer
+                .insert_or_replace_entity(&TestEntity::new("pk", row_key, value))
+                .await
+                .unwrap();
+        }
+
+        let invalid_record_fault = MockFault::Reject {
+            status: 400,
+            reason: "RequiredEntityFieldIsMissing".to_string(),
+        };
+
+        server.inject_faults(invalid_record_fault.clone(), 2);
+
+        let result = spooling_writer.replay().await;
+        assert!(matches!(result, Err(DataWriterError::SpoolIsFull { .. })));
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 2);
+        assert_eq!(backlog.rejected_amount, 1);
+
+        // Replay goes on once the rejected records are taken away
+        std::fs::remove_file(directory.join("test.spool.rejected")).unwrap();
+        server.inject_fault(invalid_record_fault);
+
+        spooling_writer.replay().await.unwrap();
+
+        let backlog = spooling_writer.get_backlog();
+        assert_eq!(backlog.records_amount, 0);
+        assert_eq!(backlog.rejected_amount, 1);
+        assert_eq!(server.get_rows_amount("test"), 1);
+
+        let rejected = std::fs::read_to_string(directory.join("test.spool.rejected")).unwrap();
+        assert!(rejected.contains("rk2"));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use my_no_sql_server_abstractions::MyNoSqlEntity;
+use serde::{Deserialize, Serialize};
+
+use super::{MockMyNoSqlServer, MyNoSqlDataWriter, RetryPolicy};
+
+// Entity the test modules share. TimeStamp is filled by the mock server on every write
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TestEntity {
+    pub partition_key: String,
+    pub row_key: String,
+    #[serde(default)]
+    pub time_stamp: i64,
+    pub value: i32,
+}
+
+impl TestEntity {
+    pub fn new(partition_key: &str, row_key: &str, value: i32) -> Self {
+        Self {
+            partition_key: partition_key.to_string(),
+            row_key: row_key.to_string(),
+            time_stamp: 0,
+            value,
+        }
+    }
+}
+
+impl MyNoSqlEntity for TestEntity {
+    const TABLE_NAME: &'static str = "test";
+
+    fn get_partition_key(&self) -> &str {
+        &self.partition_key
+    }
+
+    fn get_row_key(&self) -> &str {
+        &self.row_key
+    }
+
+    fn get_time_stamp(&self) -> i64 {
+        self.time_stamp
+    }
+}
+
+// Creates the table of TestEntity. Retry delays are short, so retry tests do not wait for long
+pub fn create_writer(server: &MockMyNoSqlServer) -> MyNoSqlDataWriter<TestEntity> {
+    create_writer_with_retry_policy(
+        server,
+        RetryPolicy {
+            initial_delay: Duration::from_millis(10),
+            ..Default::default()
+        },
+    )
+}
+
+pub fn create_writer_with_retry_policy(
+    server: &MockMyNoSqlServer,
+    retry_policy: RetryPolicy,
+) -> MyNoSqlDataWriter<TestEntity> {
+    server.create_table(TestEntity::TABLE_NAME);
+
+    MyNoSqlDataWriter::builder(server.get_settings())
+        .with_retry_policy(retry_policy)
+        .build()
+}
@@ -0,0 +1,543 @@
+use std::sync::Arc;
+
+use my_logger::LogEventCtx;
+use my_no_sql_server_abstractions::{DataSynchronizationPeriod, MyNoSqlEntity};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    json_array_chunker::JsonArrayChunker, my_no_sql_data_writer::FlUrlExt,
+    request_executor::RequestExecutor, writer_response::WriterResponse, DataWriterError,
+    ErrorContext, MyNoSqlWriterSettings,
+};
+
+const TRANSACTIONS_CONTROLLER: &str = "Transactions";
+
+// Transaction may touch several tables, so errors of the transaction itself are not bound to one
+const TRANSACTION_TABLE_NAME: &str = "";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub(crate) enum TransactionStep {
+    CleanTable {
+        #[serde(rename = "tableName")]
+        table_name: String,
+    },
+    DeletePartitions {
+        #[serde(rename = "tableName")]
+        table_name: String,
+        #[serde(rename = "partitionKeys")]
+        partition_keys: Vec<String>,
+    },
+    DeleteRows {
+        #[serde(rename = "tableName")]
+        table_name: String,
+        #[serde(rename = "partitionKey")]
+        partition_key: String,
+        #[serde(rename = "rowKeys")]
+        row_keys: Vec<String>,
+    },
+    InsertOrReplaceEntities {
+        #[serde(rename = "tableName")]
+        table_name: String,
+        entities: Vec<serde_json::Value>,
+    },
+}
+
+impl TransactionStep {
+    pub fn get_table_name(&self) -> &str {
+        match self {
+            Self::CleanTable { table_name } => table_name,
+            Self::DeletePartitions { table_name, .. } => table_name,
+            Self::DeleteRows { table_name, .. } => table_name,
+            Self::InsertOrReplaceEntities { table_name, .. } => table_name,
+        }
+    }
+}
+
+// Entities are kept as the json they were serialized to, so commit does not serialize them again
+enum PendingStep {
+    Step(TransactionStep),
+    Serialized(Vec<u8>),
+}
+
+#[derive(Deserialize, Debug)]
+struct StartTransactionResponse {
+    #[serde(rename = "transactionId")]
+    transaction_id: String,
+}
+
+/// Steps over any tables which are applied by the server all together or not at all.
+/// Steps are accumulated on the client. `commit` starts the transaction on the server,
+/// appends all the steps with a single request and commits it.
+pub struct MyNoSqlTransaction {
+    executor: RequestExecutor,
+    steps: Vec<PendingStep>,
+    max_append_body_size: Option<usize>,
+    sync_period: Option<DataSynchronizationPeriod>,
+}
+
+impl MyNoSqlTransaction {
+    pub fn new(settings: Arc<dyn MyNoSqlWriterSettings + Send + Sync + 'static>) -> Self {
+        Self::from_executor(RequestExecutor::new(settings))
+    }
+
+    pub(crate) fn from_executor(executor: RequestExecutor) -> Self {
+        Self {
+            executor,
+            steps: Vec::new(),
+            max_append_body_size: None,
+            sync_period: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.executor.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Steps are appended with several requests if they do not fit into one body of that size.
+    /// The server still applies them only on commit.
+    pub fn with_max_append_body_size(mut self, max_append_body_size: usize) -> Self {
+        self.max_append_body_size = Some(max_append_body_size);
+        self
+    }
+
+    /// Sent with the commit as the syncPeriod query param, the same way the writer sends it
+    /// with its own writes. Server default is used if it is not set.
+    pub fn with_sync_period(mut self, sync_period: DataSynchronizationPeriod) -> Self {
+        self.sync_period = Some(sync_period);
+        self
+    }
+
+    pub fn get_steps_amount(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn insert_or_replace_entity<TEntity: MyNoSqlEntity + Serialize>(
+        &mut self,
+        entity: &TEntity,
+    ) -> Result<(), DataWriterError> {
+        self.insert_or_replace_entities(std::slice::from_ref(entity))
+    }
+
+    pub fn insert_or_replace_entities<TEntity: MyNoSqlEntity + Serialize>(
+        &mut self,
+        entities: &[TEntity],
+    ) -> Result<(), DataWriterError> {
+        let entities_json =
+            serde_json::to_vec(entities).map_err(|err| DataWriterError::SerializationFailed {
+                ctx: ErrorContext::new(
+                    "transaction_insert_or_replace",
+                    TEntity::TABLE_NAME,
+                    String::new(),
+                ),
+                err,
+            })?;
+
+        self.insert_or_replace_serialized_entities::<TEntity>(&entities_json)
+    }
+
+    // Takes a json array of entities which is already serialized by the caller
+    pub(crate) fn insert_or_replace_serialized_entities<TEntity: MyNoSqlEntity>(
+        &mut self,
+        entities_json: &[u8],
+    ) -> Result<(), DataWriterError> {
+        let step_json = serialize_insert_or_replace_step(TEntity::TABLE_NAME, entities_json)
+            .map_err(|err| DataWriterError::SerializationFailed {
+                ctx: ErrorContext::new(
+                    "transaction_insert_or_replace",
+                    TEntity::TABLE_NAME,
+                    String::new(),
+                ),
+                err,
+            })?;
+
+        self.steps.push(PendingStep::Serialized(step_json));
+
+        Ok(())
+    }
+
+    pub fn delete_rows<TEntity: MyNoSqlEntity>(&mut self, partition_key: &str, row_keys: &[&str]) {
+        self.steps
+            .push(PendingStep::Step(TransactionStep::DeleteRows {
+                table_name: TEntity::TABLE_NAME.to_string(),
+                partition_key: partition_key.to_string(),
+                row_keys: row_keys.iter().map(|row_key| row_key.to_string()).collect(),
+            }));
+    }
+
+    pub fn delete_partitions<TEntity: MyNoSqlEntity>(&mut self, partition_keys: &[&str]) {
+        self.steps
+            .push(PendingStep::Step(TransactionStep::DeletePartitions {
+                table_name: TEntity::TABLE_NAME.to_string(),
+                partition_keys: partition_keys
+                    .iter()
+                    .map(|partition_key| partition_key.to_string())
+                    .collect(),
+            }));
+    }
+
+    pub fn clean_table<TEntity: MyNoSqlEntity>(&mut self) {
+        self.steps
+            .push(PendingStep::Step(TransactionStep::CleanTable {
+                table_name: TEntity::TABLE_NAME.to_string(),
+            }));
+    }
+
+    /// Sends the accumulated steps. If appending fails, the transaction is cancelled on the server.
+    /// Commit ends the transaction on the server whatever its result, so it is never cancelled
+    /// afterwards. Commit is never retried - after a transport error the outcome is unknown.
+    pub async fn commit(self) -> Result<(), DataWriterError> {
+        if self.steps.is_empty() {
+            return Ok(());
+        }
+
+        let bodies = serialize_steps(&self.steps, self.max_append_body_size).map_err(|err| {
+            DataWriterError::SerializationFailed {
+                ctx: ErrorContext::new("transaction_append", TRANSACTION_TABLE_NAME, String::new()),
+                err,
+            }
+        })?;
+
+        let mut response = self
+            .post("transaction_start", "Start", true, None, None, None)
+            .await?;
+
+        let transaction_id = response
+            .deserialize_entity::<StartTransactionResponse>()
+            .await?
+            .transaction_id;
+
+        if let Err(err) = self.append(&transaction_id, bodies).await {
+            self.cancel_on_server(&transaction_id).await;
+            return Err(err);
+        }
+
+        self.post(
+            "transaction_commit",
+            "Commit",
+            false,
+            Some(transaction_id.as_str()),
+            self.sync_period.as_ref(),
+            None,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Discards the accumulated steps. Nothing is sent to the server before `commit`.
+    pub fn cancel(self) {}
+
+    // Appending the same body twice leaves the same state after commit, so appends are retried
+    async fn append(
+        &self,
+        transaction_id: &str,
+        bodies: Vec<Vec<u8>>,
+    ) -> Result<(), DataWriterError> {
+        for body in bodies {
+            self.post(
+                "transaction_append",
+                "Append",
+                true,
+                Some(transaction_id),
+                None,
+                Some(body),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn cancel_on_server(&self, transaction_id: &str) {
+        let result = self
+            .post(
+                "transaction_cancel",
+                "Cancel",
+                true,
+                Some(transaction_id),
+                None,
+                None,
+            )
+            .await;
+
+        if let Err(err) = result {
+            my_logger::LOGGER.write_warning(
+                "transaction_cancel",
+                format!("Can not cancel transaction {}: {}", transaction_id, err),
+                LogEventCtx::new().add("TransactionId", transaction_id),
+            );
+        }
+    }
+
+    // Goes through the executor of the writer, so it shares the timeout, retries and limits
+    async fn post(
+        &self,
+        process_name: &'static str,
+        action: &'static str,
+        idempotent: bool,
+        transaction_id: Option<&str>,
+        sync_period: Option<&DataSynchronizationPeriod>,
+        body: Option<Vec<u8>>,
+    ) -> Result<WriterResponse, DataWriterError> {
+        let body = &body;
+
+        let mut response = self
+            .executor
+            .execute(
+                process_name,
+                TRANSACTION_TABLE_NAME,
+                idempotent,
+                || async move {
+                    let mut fl_url = self
+                        .executor
+                        .get_fl_url()
+                        .await
+                        .append_path_segment(TRANSACTIONS_CONTROLLER)
+                        .append_path_segment(action);
+
+                    if let Some(transaction_id) = transaction_id {
+                        fl_url = fl_url.append_query_param("transactionId", Some(transaction_id));
+                    }
+
+                    if let Some(sync_period) = sync_period {
+                        fl_url = fl_url.append_data_sync_period(sync_period);
+                    }
+
+                    fl_url.post(body.clone()).await
+                },
+            )
+            .await?;
+
+        response.check_error().await?;
+
+        Ok(response)
+    }
+}
+
+// Every body is a json array of steps.
+// Step which alone is bigger than max_body_size goes as a separate body
+fn serialize_steps(
+    steps: &[PendingStep],
+    max_body_size: Option<usize>,
+) -> Result<Vec<Vec<u8>>, serde_json::Error> {
+    let mut chunker = JsonArrayChunker::new(usize::MAX, max_body_size.unwrap_or(usize::MAX));
+
+    for step in steps {
+        match step {
+            PendingStep::Step(step) => chunker.push(&serde_json::to_vec(step)?),
+            PendingStep::Serialized(step_json) => chunker.push(step_json),
+        }
+    }
+
+    Ok(chunker.finish().into_iter().map(|(_, body)| body).collect())
+}
+
+// Same json as TransactionStep::InsertOrReplaceEntities, built around the serialized entities
+fn serialize_insert_or_replace_step(
+    table_name: &str,
+    entities_json: &[u8],
+) -> Result<Vec<u8>, serde_json::Error> {
+    let mut step_json = Vec::with_capacity(entities_json.len() + table_name.len() + 64);
+
+    step_json.extend_from_slice(br#"{"type":"InsertOrReplaceEntities","tableName":"#);
+    serde_json::to_writer(&mut step_json, table_name)?;
+    step_json.extend_from_slice(br#","entities":"#);
+    step_json.extend_from_slice(entities_json);
+    step_json.push(b'}');
+
+    Ok(step_json)
+}
+
+#[cfg(test)]
+mod tests {
+    use my_no_sql_server_abstractions::MyNoSqlEntity;
+    use serde::Serialize;
+
+    use super::{serialize_insert_or_replace_step, TransactionStep};
+    use crate::{
+        test_fixtures::{create_writer, TestEntity},
+        DataWriterError, MockFault, MockMyNoSqlServer, MyNoSqlDataWriter, RequestLimiter,
+        RequestLimiterParams,
+    };
+
+    #[test]
+    fn test_steps_serialization() {
+        let steps = vec![
+            TransactionStep::DeleteRows {
+                table_name: TestEntity::TABLE_NAME.to_string(),
+                partition_key: "pk".to_string(),
+                row_keys: vec!["rk".to_string()],
+            },
+            TransactionStep::InsertOrReplaceEntities {
+                table_name: TestEntity::TABLE_NAME.to_string(),
+                entities: vec![serde_json::to_value(TestEntity::new("pk", "rk", 1)).unwrap()],
+            },
+        ];
+
+        assert_eq!(
+            serde_json::to_string(&steps).unwrap(),
+            r#"[{"type":"DeleteRows","tableName":"test","partitionKey":"pk","rowKeys":["rk"]},{"type":"InsertOrReplaceEntities","tableName":"test","entities":[{"PartitionKey":"pk","RowKey":"rk","TimeStamp":0,"Value":1}]}]"#
+        );
+
+        let entities_json = serde_json::to_vec(&[TestEntity::new("pk", "rk", 1)]).unwrap();
+
+        let step_json = serialize_insert_or_replace_step("test", &entities_json).unwrap();
+        assert_eq!(step_json, serde_json::to_vec(&steps[1]).unwrap());
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct BalanceEntity {
+        partition_key: String,
+        row_key: String,
+        balance: i32,
+    }
+
+    impl MyNoSqlEntity for BalanceEntity {
+        const TABLE_NAME: &'static str = "balances";
+
+        fn get_partition_key(&self) -> &str {
+            &self.partition_key
+        }
+
+        fn get_row_key(&self) -> &str {
+            &self.row_key
+        }
+
+        fn get_time_stamp(&self) -> i64 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_is_applied_to_all_tables_or_none() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        writer
+            .insert_entity(&TestEntity::new("pk", "rk", 1))
+            .await
+            .unwrap();
+
+        let balance = BalanceEntity {
+            partition_key: "client".to_string(),
+            row_key: "USD".to_string(),
+            balance: 10,
+        };
+
+        let mut transaction = writer.create_transaction();
+        transaction.delete_rows::<TestEntity>("pk", &["rk"]);
+        transaction.insert_or_replace_entity(&balance).unwrap();
+
+        let result = transaction.commit().await;
+        assert!(matches!(result, Err(DataWriterError::TableNotFound { .. })));
+        assert_eq!(server.get_rows_amount("test"), 1);
+
+        server.create_table(BalanceEntity::TABLE_NAME);
+
+        let mut transaction = writer.create_transaction();
+        transaction.delete_rows::<TestEntity>("pk", &["rk"]);
+        transaction.insert_or_replace_entity(&balance).unwrap();
+        transaction.commit().await.unwrap();
+
+        assert_eq!(server.get_rows_amount("test"), 0);
+        assert_eq!(server.get_rows_amount("balances"), 1);
+
+        let paths: Vec<String> = server
+            .get_requests()
+            .into_iter()
+            .map(|request| request.path)
+            .filter(|path| path.starts_with("/Transactions/"))
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "/Transactions/Start",
+                "/Transactions/Append",
+                "/Transactions/Commit",
+                "/Transactions/Start",
+                "/Transactions/Append",
+                "/Transactions/Commit",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_is_cancelled_when_append_fails() {
+        let server = MockMyNoSqlServer::start().await;
+        let writer = create_writer(&server);
+
+        server.inject_fault_for_path(
+            "/Transactions/Append",
+            MockFault::ServerError {
+                status: 503,
+                body: "Append failed".to_string(),
+            },
+        );
+        server.inject_fault_for_path(
+            "/Transactions/Append",
+            MockFault::UnknownReason {
+                status: 400,
+                reason: "AppendRejected".to_string(),
+            },
+        );
+
+        let mut transaction = writer.create_transaction();
+        transaction
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk", 1))
+            .unwrap();
+
+        assert!(transaction.commit().await.is_err());
+        assert_eq!(server.get_rows_amount("test"), 0);
+
+        let paths: Vec<String> = server
+            .get_requests()
+            .into_iter()
+            .map(|request| request.path)
+            .filter(|path| path.starts_with("/Transactions/"))
+            .collect();
+
+        // Failed append is retried as any idempotent request, rejected one cancels the transaction
+        assert_eq!(
+            paths,
+            vec![
+                "/Transactions/Start",
+                "/Transactions/Append",
+                "/Transactions/Append",
+                "/Transactions/Cancel",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_requests_are_limited() {
+        let server = MockMyNoSqlServer::start().await;
+        server.create_table(TestEntity::TABLE_NAME);
+
+        let request_limiter = RequestLimiter::new(RequestLimiterParams {
+            max_concurrent_requests: Some(1),
+            max_requests_per_second: None,
+        });
+
+        let writer: MyNoSqlDataWriter<TestEntity> =
+            MyNoSqlDataWriter::builder(server.get_settings())
+                .with_request_limiter(request_limiter.clone())
+                .build();
+
+        let mut transaction = writer.create_transaction();
+        transaction
+            .insert_or_replace_entity(&TestEntity::new("pk", "rk", 1))
+            .unwrap();
+        transaction.commit().await.unwrap();
+
+        assert_eq!(server.get_rows_amount("test"), 1);
+
+        // Start, Append and Commit
+        let metrics = request_limiter.get_metrics();
+        assert_eq!(metrics.acquired_amount, 3);
+        assert_eq!(metrics.in_flight_amount, 0);
+    }
+}
@@ -1,6 +1,7 @@
 use flurl::FlUrl;
 use rust_extensions::date_time::DateTimeAsMicroseconds;
 
+#[derive(Clone)]
 pub struct UpdateReadStatistics {
     pub update_partition_read_access: bool,
     pub update_row_read_access: bool,
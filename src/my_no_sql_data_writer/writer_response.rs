@@ -0,0 +1,178 @@
+use flurl::FlUrlResponse;
+use futures::Stream;
+use my_logger::LogEventCtx;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    error::IntoDataWriterError, json_array_reader::read_json_array, DataWriterError, ErrorContext,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OperationFailHttpContract {
+    pub reason: String,
+    pub message: String,
+}
+
+// Response which knows the operation it belongs to, so every error produced from it carries the context
+pub(crate) struct WriterResponse {
+    response: FlUrlResponse,
+    ctx: ErrorContext,
+}
+
+impl WriterResponse {
+    pub fn new(response: FlUrlResponse, ctx: ErrorContext) -> Self {
+        let ctx = ErrorContext {
+            url: response.url.to_string(),
+            ..ctx
+        }
+        .with_status_code(response.get_status_code());
+
+        Self { response, ctx }
+    }
+
+    pub fn get_status_code(&self) -> u16 {
+        self.response.get_status_code()
+    }
+
+    pub fn is_ok_result(&self) -> bool {
+        self.get_status_code() >= 200 && self.get_status_code() < 300
+    }
+
+    pub async fn get_body(&mut self) -> Result<&[u8], DataWriterError> {
+        let ctx = &self.ctx;
+        self.response
+            .get_body()
+            .await
+            .map_err(|err| err.into_data_writer_error(ctx.clone()))
+    }
+
+    pub async fn check_error(&mut self) -> Result<(), DataWriterError> {
+        if self.is_ok_result() {
+            return Ok(());
+        }
+
+        let status_code = self.get_status_code();
+        let ctx = self.ctx.clone();
+        let body = self.get_body().await?;
+        let err = deserialize_error(status_code, body, ctx);
+
+        my_logger::LOGGER.write_error(
+            format!("FlUrlRequest to {}", self.ctx.url),
+            format!("{:?}", err),
+            LogEventCtx::new().add("Operation", self.ctx.operation),
+        );
+
+        Err(err)
+    }
+
+    pub async fn deserialize_entity<TEntity: DeserializeOwned>(
+        &mut self,
+    ) -> Result<TEntity, DataWriterError> {
+        let ctx = self.ctx.clone();
+        let src = self.get_body().await?;
+
+        match serde_json::from_slice(src) {
+            Ok(result) => Ok(result),
+            Err(err) => Err(DataWriterError::Error {
+                ctx,
+                message: format!("Failed to deserialize entity: {:?}", err),
+            }),
+        }
+    }
+
+    pub async fn deserialize_entities<TEntity: DeserializeOwned>(
+        &mut self,
+    ) -> Result<Vec<TEntity>, DataWriterError> {
+        self.deserialize_entity::<Vec<TEntity>>().await
+    }
+
+    // Takes the body over, so entities are parsed while the body is being received.
+    // Only the current chunk and the entity being parsed are kept in memory
+    pub fn into_entities_stream<TEntity: DeserializeOwned>(
+        self,
+    ) -> impl Stream<Item = Result<TEntity, DataWriterError>> {
+        let body = self.response.into_hyper_response().into_body();
+        read_json_array(body, self.ctx)
+    }
+}
+
+fn deserialize_error(status_code: u16, body: &[u8], ctx: ErrorContext) -> DataWriterError {
+    if status_code >= 500 {
+        return DataWriterError::ServerError {
+            ctx,
+            status: status_code,
+            body: String::from_utf8_lossy(body).to_string(),
+        };
+    }
+
+    match serde_json::from_slice::<OperationFailHttpContract>(body) {
+        Ok(fail_contract) => {
+            let message = fail_contract.message;
+            match fail_contract.reason.as_str() {
+                "TableAlreadyExists" => DataWriterError::TableAlreadyExists { ctx, message },
+                "TableNotFound" => DataWriterError::TableNotFound { ctx, message },
+                "RecordAlreadyExists" => DataWriterError::RecordAlreadyExists { ctx, message },
+                "RecordIsChanged" => DataWriterError::RecordIsChanged { ctx, message },
+                "RecordNotFound" => DataWriterError::RecordNotFound { ctx, message },
+                "RequiredEntityFieldIsMissing" => {
+                    DataWriterError::RequiredEntityFieldIsMissing { ctx, message }
+                }
+                "JsonParseFail" => DataWriterError::ServerCouldNotParseJson { ctx, message },
+                reason => DataWriterError::Error {
+                    ctx,
+                    message: format!("Not supported error. Reason: {}. {}", reason, message),
+                },
+            }
+        }
+        Err(err) => DataWriterError::Error {
+            ctx,
+            message: format!(
+                "Failed to deserialize error. Err: {:?}. Body: {}",
+                err,
+                String::from_utf8_lossy(body)
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DataWriterError, ErrorContext};
+
+    #[test]
+    fn test_deserialize_error() {
+        let ctx = ErrorContext::new("insert_entity", "test", "http://localhost".to_string())
+            .with_status_code(409);
+
+        let err = super::deserialize_error(
+            409,
+            br#"{"reason":"RecordAlreadyExists","message":"Record exists"}"#,
+            ctx.clone(),
+        );
+        assert!(matches!(err, DataWriterError::RecordAlreadyExists { .. }));
+        assert_eq!(err.get_status_code(), Some(409));
+        assert_eq!(err.get_ctx().operation, "insert_entity");
+
+        let err = super::deserialize_error(503, b"Service Unavailable", ctx.clone());
+        assert!(matches!(
+            err,
+            DataWriterError::ServerError { status: 503, .. }
+        ));
+        assert!(err.is_retryable());
+
+        let err = super::deserialize_error(
+            404,
+            br#"{"reason":"RecordNotFound","message":"Record not found"}"#,
+            ctx.clone(),
+        );
+        assert!(matches!(err, DataWriterError::RecordNotFound { .. }));
+
+        // 404 of a proxy or a wrong url is not a business outcome
+        let err = super::deserialize_error(404, b"Not Found", ctx.clone());
+        assert!(matches!(err, DataWriterError::Error { .. }));
+
+        let err = super::deserialize_error(400, b"not a json", ctx);
+        assert!(matches!(err, DataWriterError::Error { .. }));
+        assert!(!err.is_retryable());
+    }
+}